@@ -32,7 +32,10 @@ defmodule XlsxWriter do
 
   ### Core Functions
   - `generate/1` - Generate XLSX binary from sheets
-  - `new_sheet/1` - Create a new worksheet
+  - `generate_file/3` - Generate an XLSX file straight to disk, without building a binary
+  - `new_sheet/2` - Create a new worksheet, optionally in constant or low memory mode
+  - `new_chartsheet/4` - Create a tab holding a single full-page chart
+  - `XlsxWriter.Workbook` - Build a workbook in batches, e.g. streaming rows for very large exports
 
   ### Writing Data
   - `write/5` - Write any value to a cell
@@ -42,6 +45,7 @@ defmodule XlsxWriter do
   - `write_image/4` - Embed image
   - `write_comment/5` - Add comment/note to cell
   - `write_blank/4` - Write formatted blank cell
+  - `generate/2` with `:styles` - Define named format lists once and reference them with `style:`
 
   ### Layout & Structure
   - `set_column_width/3`, `set_row_height/3` - Size columns and rows
@@ -49,7 +53,34 @@ defmodule XlsxWriter do
   - `freeze_panes/3` - Lock rows/columns when scrolling
   - `merge_range/7` - Combine multiple cells
   - `hide_row/2`, `hide_column/2` - Hide rows/columns
+  - `group_rows/4`, `group_columns/4` - Outline groups with levels and collapsed state
+  - `set_outline_summary/2` - Place summary rows above/below and columns left/right of their groups
+  - `hide_sheet/1` - Hide a whole sheet, e.g. one holding dropdown lists
   - `set_autofilter/5` - Add dropdown filters to headers
+  - `add_conditional_format/7` - Highlight cells by value, text, date, duplicates or rank, formulas, or add color scales, data bars and icon sets
+
+  ### Data Entry
+  - `add_data_validation/7` - Restrict input with dropdown lists, number, date, time, text length or formula rules
+
+  ### Protection
+  - `generate/2` with `:password` - Encrypt the file with a password-to-open
+  - `protect_sheet/2` - Protect a sheet with an optional password, leaving unlocked cells editable
+  - `generate/2` with `:protect_workbook` and `:read_only_recommended` - Lock the sheet structure or suggest read-only opening
+
+  ### Printing
+  - `set_page_setup/2` - Orientation, paper size, margins, centering, scaling, page order and numbering
+  - `set_header_footer/2` - Printed headers and footers with page fields, fonts and images
+  - `set_print_area/5` - Limit printing to a range
+  - `set_repeat_rows/3`, `set_repeat_columns/3` - Repeat title rows/columns on every page
+  - `set_page_breaks/2`, `set_vertical_page_breaks/2` - Manual page breaks
+
+  ### Tables
+  - `add_table/6` - Turn a range into an Excel table with a style, header options, column names, total row and calculated columns
+  - `write_table/5` - Write a header row and data rows as an Excel table in one call
+
+  ### Charts
+  - `insert_chart/6` - Insert a column, bar, line, area, pie, doughnut, scatter or radar chart
+  - `add_sparkline/5`, `add_sparkline_group/7` - Add in-cell line, column or win/loss sparklines
 
   See the [full documentation](https://hexdocs.pm/xlsx_writer) for detailed function references.
   """
@@ -67,6 +98,27 @@ defmodule XlsxWriter do
   - `sheets` - A list of `{sheet_name, instructions}` tuples
   - `opts` - Optional keyword list:
     - `:properties` - A `%XlsxWriter.WorkbookProperties{}` struct with document metadata
+    - `:defined_names` - A list of `{name, formula}` tuples, e.g.
+      `{"TaxRate", "=Settings!$B$2"}`. Names are workbook-wide unless
+      prefixed with a sheet name, e.g. `{"Settings!Region", "=Settings!$A$1"}`,
+      which makes them local to that sheet. Use the names in formulas,
+      validation lists and chart ranges
+    - `:protect_workbook` - `true` or `[password: "secret"]` to stop users
+      from adding, deleting, renaming, moving or unhiding sheets. As with
+      `protect_sheet/2`, the password only deters casual changes
+    - `:read_only_recommended` - When `true`, Excel suggests opening the file
+      read-only
+    - `:password` - Encrypt the file so it only opens with this password.
+      Uses ECMA-376 Agile encryption (AES-256, SHA-512), as Excel does. The
+      result is an encrypted OLE container rather than a plain zip
+    - `:memory` - Memory mode of the worksheets, see `new_sheet/2`:
+      `:standard` (default), `:constant` or `:low`
+    - `:tempdir` - Directory for the temp files of constant and low memory
+      worksheets. Defaults to the system temp directory
+    - `:styles` - A list of `{name, formats}` tuples, e.g.
+      `{"currency", [{:num_format, "$#,##0.00"}]}`. Cells written with
+      `style: "currency"` (or `style: 0`, the index in the list) use that
+      format list without carrying it in every instruction
 
   ## Returns
 
@@ -85,6 +137,12 @@ defmodule XlsxWriter do
       props = %XlsxWriter.WorkbookProperties{author: "John", title: "Report"}
       {:ok, content} = XlsxWriter.generate([sheet], properties: props)
 
+      # With a defined name used in a formula
+      settings = XlsxWriter.new_sheet("Settings") |> XlsxWriter.write(1, 1, 0.2)
+      sheet = XlsxWriter.new_sheet("Prices") |> XlsxWriter.write_formula(0, 0, "=100*TaxRate")
+      {:ok, content} =
+        XlsxWriter.generate([sheet, settings], defined_names: [{"TaxRate", "=Settings!$B$2"}])
+
   """
   def generate(sheets, opts \\ []) when is_list(sheets) do
     # It might not be important to reverse the instructions here
@@ -94,13 +152,15 @@ defmodule XlsxWriter do
         {name, Enum.reverse(instructions)}
       end)
 
+    options = XlsxWriter.WorkbookOptions.from_opts(opts)
+
     result =
       case Keyword.get(opts, :properties) do
         nil ->
-          RustXlsxWriter.write(sheets)
+          RustXlsxWriter.write(sheets, options)
 
         %XlsxWriter.WorkbookProperties{} = properties ->
-          RustXlsxWriter.write_with_properties(sheets, properties)
+          RustXlsxWriter.write_with_properties(sheets, properties, options)
       end
 
     case result do
@@ -112,24 +172,135 @@ defmodule XlsxWriter do
     end
   end
 
+  @doc """
+  Generates an Excel file and writes it straight to `path`.
+
+  Unlike `generate/2` followed by `File.write!/2`, the file content is never
+  copied into a binary, so large workbooks only exist once in memory. When
+  the sheets use constant or low memory mode (see `new_sheet/2`) the package
+  is streamed to disk as it is written.
+
+  Workbooks that are encrypted, structure protected or have first/even page
+  headers are rewritten after saving, which happens in memory before the
+  file is written.
+
+  ## Parameters
+
+  - `sheets` - A list of `{sheet_name, instructions}` tuples
+  - `path` - The output file path
+  - `opts` - Same options as `generate/2`
+
+  ## Returns
+
+  - `:ok` on success
+  - `{:error, reason}` on failure, including when the file can't be written
+
+  ## Examples
+
+      sheet = XlsxWriter.new_sheet("Test") |> XlsxWriter.write(0, 0, "Hello")
+      :ok = XlsxWriter.generate_file([sheet], "output.xlsx")
+
+  """
+  def generate_file(sheets, path, opts \\ []) when is_list(sheets) and is_binary(path) do
+    sheets =
+      Enum.map(sheets, fn {name, instructions} ->
+        {name, Enum.reverse(instructions)}
+      end)
+
+    options = XlsxWriter.WorkbookOptions.from_opts(opts)
+
+    result =
+      case Keyword.get(opts, :properties) do
+        nil ->
+          RustXlsxWriter.write_to_file(sheets, path, options)
+
+        %XlsxWriter.WorkbookProperties{} = properties ->
+          RustXlsxWriter.write_to_file_with_properties(sheets, path, properties, options)
+      end
+
+    case result do
+      {:ok, _} -> :ok
+      other -> other
+    end
+  end
+
   @doc """
   Creates a new empty sheet with the given name.
 
+  By default every cell of a sheet is kept in memory until the file is
+  generated. For very large sheets, constant and low memory modes write each
+  row to a temp file as soon as a later row is written, keeping memory use
+  flat:
+
+  - `:constant` - Strings are stored inline in the cells, so repeated
+    strings make the file larger
+  - `:low` - Strings go to the shared string table, which stays in memory
+
+  In both modes rows must be written in order. Anything that targets a row
+  that has already been written out is ignored, so write headers first:
+
+  - Writes, formats, heights and hidden flags for the row
+  - The value of a `merge_range/7` starting in the row (the merge is kept)
+  - The header cells of a table added with `add_table/6` whose header row
+    was written out; add tables before writing past their first row
+  - `autofit/1` only measures the row that is still in memory
+
+  Row outline groups (`group_rows/4`) aren't supported and return an error.
+  Column groups, outline summary positions, conditional format priorities
+  and header/footer variants are applied when the workbook is saved and work
+  in every mode.
+
   ## Parameters
 
   - `name` - The name of the sheet (must be a string)
+  - `opts` - Optional keyword list:
+    - `:memory` - `:standard`, `:constant` or `:low`. Defaults to the
+      `:memory` option of `generate/2`
 
   ## Returns
 
-  A sheet tuple `{name, []}` ready for writing data.
+  A sheet tuple `{name, instructions}` ready for writing data.
 
   ## Examples
 
       iex> XlsxWriter.new_sheet("My Sheet")
       {"My Sheet", []}
 
+      iex> XlsxWriter.new_sheet("Export", memory: :constant)
+      {"Export", [{:set_memory_mode, :constant}]}
+
+  """
+  def new_sheet(name, opts \\ []) when is_binary(name) do
+    case Keyword.get(opts, :memory) do
+      nil -> {name, []}
+      mode -> {name, [{:set_memory_mode, mode}]}
+    end
+  end
+
+  @doc """
+  Creates a new chartsheet: a workbook tab holding a single full-page chart.
+
+  Chartsheets have no cells of their own, so the series must reference data
+  on other worksheets. They can be placed anywhere in the list passed to
+  `generate/2`, including first, ahead of the sheets holding the data.
+
+  Takes the same `type`, `series` and `opts` as `insert_chart/6`; anchor
+  options (`:offset`, `:size`) do not apply. Sheet-level instructions such as
+  `set_tab_color/2` can still be piped onto the chartsheet.
+
+  ## Examples
+
+      iex> XlsxWriter.new_chartsheet("Summary Chart", :pie, [[values: "Data!$B$2:$B$5"]])
+      {"Summary Chart", [{:chartsheet, :pie, [[values: "Data!$B$2:$B$5"]], []}]}
+
   """
-  def new_sheet(name) when is_binary(name), do: {name, []}
+  def new_chartsheet(name, type, series, opts \\ [])
+      when is_binary(name) and is_atom(type) and is_list(series) do
+    {name,
+     [
+       {:chartsheet, type, Enum.map(series, &to_chart_series/1), to_chart_options(opts)}
+     ]}
+  end
 
   @doc """
   Writes a value to a specific cell in the sheet.
@@ -157,6 +328,9 @@ defmodule XlsxWriter do
     - `{:rotation, angle}` - Rotate text (-90 to 90 degrees, or 270 for vertical stacked text)
     - `:shrink` - Shrink text to fit within the cell width
     - `{:indent, level}` - Indent text by the given level (integer)
+    - `:locked`, `:unlocked` - Lock or unlock the cell for editing once the
+      sheet is protected (cells are locked by default, see `protect_sheet/2`)
+    - `:hidden` - Hide the cell formula once the sheet is protected
     - `{:align, :left | :center | :right}` - Horizontal text alignment
     - `{:valign, :top | :center | :bottom | :justify | :distributed}` - Vertical text alignment
     - `{:num_format, format_string}` - Custom number format
@@ -176,6 +350,9 @@ defmodule XlsxWriter do
     - `{:border_bottom_color, hex_color}` - Bottom border color
     - `{:border_left_color, hex_color}` - Left border color
     - `{:border_right_color, hex_color}` - Right border color
+  - `:style` - Name or index of a style from the `:styles` option of
+    `generate/2`, used instead of `:format`. A big export sending the same
+    format list with every cell can define it once and reference it here
 
   ## Border Styles
 
@@ -209,15 +386,22 @@ defmodule XlsxWriter do
       iex> sheet = XlsxWriter.write(sheet, 0, 0, "Bordered", format: [{:border, :thin}])
       iex> {"Test", [{:write, 0, 0, {:string_with_format, "Bordered", [{:border, :thin}]}}]} = sheet
 
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.write(sheet, 0, 0, 9.99, style: "currency")
+      iex> {"Test", [{:write, 0, 0, {:number_with_style, 9.99, "currency"}}]} = sheet
+
   """
   def write({name, instructions}, row, col, val, opts \\ []) do
     Validation.validate_cell_position!(row, col)
 
-    case Keyword.get(opts, :format) do
-      nil ->
+    case style_or_format(opts) do
+      {:style, style} ->
+        {name, [{:write, row, col, to_styled_rust_val(val, style)} | instructions]}
+
+      {:format, nil} ->
         {name, [{:write, row, col, to_rust_val(val)} | instructions]}
 
-      formats when is_list(formats) ->
+      {:format, formats} when is_list(formats) ->
         write_with_format({name, instructions}, row, col, val, formats)
     end
   end
@@ -236,6 +420,7 @@ defmodule XlsxWriter do
   ## Options
 
   - `:format` - A list of format specifications
+  - `:style` - Name or index of a workbook style, see `write/5`
 
   ## Returns
 
@@ -255,11 +440,14 @@ defmodule XlsxWriter do
   def write_formula({name, instructions}, row, col, val, opts \\ []) do
     Validation.validate_cell_position!(row, col)
 
-    case Keyword.get(opts, :format) do
-      nil ->
+    case style_or_format(opts) do
+      {:style, style} ->
+        {name, [{:write, row, col, {:formula_with_style, val, style}} | instructions]}
+
+      {:format, nil} ->
         {name, [{:write, row, col, {:formula, val}} | instructions]}
 
-      formats when is_list(formats) ->
+      {:format, formats} when is_list(formats) ->
         Validation.validate_formats!(formats)
         {name, [{:write, row, col, {:formula_with_format, val, formats}} | instructions]}
     end
@@ -295,11 +483,14 @@ defmodule XlsxWriter do
       when is_boolean(val) do
     Validation.validate_cell_position!(row, col)
 
-    case Keyword.get(opts, :format) do
-      nil ->
+    case style_or_format(opts) do
+      {:style, style} ->
+        {name, [{:write, row, col, {:boolean_with_style, val, style}} | instructions]}
+
+      {:format, nil} ->
         {name, [{:write, row, col, {:boolean, val}} | instructions]}
 
-      formats when is_list(formats) ->
+      {:format, formats} when is_list(formats) ->
         Validation.validate_formats!(formats)
 
         {name,
@@ -323,6 +514,9 @@ defmodule XlsxWriter do
     - `:text` - Display text (different from URL)
     - `:format` - Format specifications
 
+  Named styles aren't supported for URLs; passing `:style` raises an
+  `ArgumentError`.
+
   ## Returns
 
   Updated sheet tuple with the new URL instruction.
@@ -341,6 +535,7 @@ defmodule XlsxWriter do
   def write_url({name, instructions}, row, col, url, opts \\ [])
       when is_binary(url) do
     Validation.validate_cell_position!(row, col)
+    reject_style!(opts, "URLs")
     text = Keyword.get(opts, :text)
     formats = Keyword.get(opts, :format)
 
@@ -375,7 +570,8 @@ defmodule XlsxWriter do
   - `sheet` - The sheet tuple `{name, instructions}`
   - `row` - The row index (0-based)
   - `col` - The column index (0-based)
-  - `opts` - Keyword list with `:format` specifications
+  - `opts` - Keyword list with `:format` specifications, or a `:style`
+    name or index (see `write/5`)
 
   ## Returns
 
@@ -390,12 +586,40 @@ defmodule XlsxWriter do
   """
   def write_blank({name, instructions}, row, col, opts \\ []) do
     Validation.validate_cell_position!(row, col)
-    formats = Keyword.get(opts, :format, [])
 
-    if is_list(formats) && formats != [],
-      do: Validation.validate_formats!(formats)
+    case style_or_format(opts) do
+      {:style, style} ->
+        {name, [{:write, row, col, {:blank_with_style, style}} | instructions]}
+
+      {:format, formats} ->
+        formats = formats || []
+
+        if is_list(formats) && formats != [],
+          do: Validation.validate_formats!(formats)
+
+        {name, [{:write, row, col, {:blank, formats}} | instructions]}
+    end
+  end
+
+  defp style_or_format(opts) do
+    case {Keyword.get(opts, :style), Keyword.get(opts, :format)} do
+      {nil, formats} ->
+        {:format, formats}
+
+      {style, nil} ->
+        Validation.validate_style!(style)
+        {:style, style}
+
+      {_style, _formats} ->
+        raise ArgumentError, "Pass either a :style or a :format, not both"
+    end
+  end
 
-    {name, [{:write, row, col, {:blank, formats}} | instructions]}
+  defp reject_style!(opts, kind) do
+    if Keyword.has_key?(opts, :style) do
+      raise ArgumentError,
+            "The :style option isn't supported for #{kind}, use :format instead"
+    end
   end
 
   defp write_with_format(sheet, row, col, nil, formats) do
@@ -505,6 +729,9 @@ defmodule XlsxWriter do
   - `opts` - Optional keyword list with:
     - `:format` - Cell-level formatting (alignment, borders, background, etc.)
 
+  Named styles aren't supported for rich strings; passing `:style` raises an
+  `ArgumentError`.
+
   ## Segment Format Options
 
   Each segment can have text formatting options:
@@ -552,6 +779,7 @@ defmodule XlsxWriter do
   def write_rich_string({name, instructions}, row, col, segments, opts \\ []) do
     Validation.validate_cell_position!(row, col)
     Validation.validate_rich_string_segments!(segments)
+    reject_style!(opts, "rich strings")
 
     case Keyword.get(opts, :format) do
       nil ->
@@ -848,10 +1076,97 @@ defmodule XlsxWriter do
     {name, [{:set_column_hidden, col} | instructions]}
   end
 
+  @doc """
+  Groups a range of rows into an outline that can be expanded and collapsed.
+
+  A row gets the highest level of the groups it belongs to, so nested groups
+  are given a higher `:level` than the group around them.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `first_row` - The first row of the group (0-based)
+  - `last_row` - The last row of the group (0-based)
+  - `opts` - Keyword list:
+    - `:level` - Outline level from 1 (default) to 7
+    - `:collapsed` - Collapse the group, hiding its rows
+
+  ## Returns
+
+  Updated sheet tuple with the group rows instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.group_rows(sheet, 1, 4, level: 2, collapsed: true)
+      iex> {"Test", [{:group_rows, 1, 4, [level: 2, collapsed: true]}]} = sheet
+
+  """
+  def group_rows({name, instructions}, first_row, last_row, opts \\ []) do
+    {name, [{:group_rows, first_row, last_row, opts} | instructions]}
+  end
+
+  @doc """
+  Groups a range of columns into an outline that can be expanded and collapsed.
+
+  Takes the same options as `group_rows/4`.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `first_col` - The first column of the group (0-based)
+  - `last_col` - The last column of the group (0-based)
+  - `opts` - Keyword list with `:level` and `:collapsed`
+
+  ## Returns
+
+  Updated sheet tuple with the group columns instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.group_columns(sheet, 1, 3)
+      iex> {"Test", [{:group_columns, 1, 3, []}]} = sheet
+
+  """
+  def group_columns({name, instructions}, first_col, last_col, opts \\ []) do
+    {name, [{:group_columns, first_col, last_col, opts} | instructions]}
+  end
+
+  @doc """
+  Sets where the summary rows and columns of outline groups are.
+
+  This places the `[+]`/`[-]` buttons next to the totals of each group.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `opts` - Keyword list:
+    - `:rows` - `:below` (default) or `:above` the group
+    - `:columns` - `:right` (default) or `:left` of the group
+
+  ## Returns
+
+  Updated sheet tuple with the outline summary instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.set_outline_summary(sheet, rows: :above)
+      iex> {"Test", [{:set_outline_summary, [rows: :above]}]} = sheet
+
+  """
+  def set_outline_summary({name, instructions}, opts) do
+    {name, [{:set_outline_summary, opts} | instructions]}
+  end
+
   @doc """
   Sets an autofilter on a range of cells.
 
   Adds dropdown filter buttons to the specified range, typically used on header rows.
+  For header-plus-rows exports, `write_table/5` writes the rows as a real Excel
+  table that includes the filter buttons along with banded styles and
+  structured references.
 
   ## Parameters
 
@@ -940,137 +1255,458 @@ defmodule XlsxWriter do
   end
 
   @doc """
-  Merges a range of cells into a single cell.
+  Hides the sheet.
 
-  The merged cell will contain the specified value and formatting.
-  All merged cells will appear as one cell in Excel.
+  Hidden sheets are useful for lookup data such as the source of a dropdown
+  list (see `add_data_validation/7`). At least one sheet in the workbook must
+  stay visible, and the first sheet is the active one, so don't hide it.
 
   ## Parameters
 
   - `sheet` - The sheet tuple `{name, instructions}`
-  - `first_row` - The first row of the merge range (0-based)
-  - `first_col` - The first column of the merge range (0-based)
-  - `last_row` - The last row of the merge range (0-based)
-  - `last_col` - The last column of the merge range (0-based)
-  - `val` - The value to write in the merged cell
-  - `opts` - Optional keyword list with formatting options
 
   ## Returns
 
-  Updated sheet tuple with the merge range instruction.
+  Updated sheet tuple with the hide sheet instruction.
 
   ## Examples
 
-      # Merge cells A1:D1 with centered title
-      iex> sheet = XlsxWriter.new_sheet("Test")
-      iex> sheet = XlsxWriter.merge_range(sheet, 0, 0, 0, 3, "Title", format: [:bold, {:align, :center}])
-      iex> {"Test", [{:merge_range, 0, 0, 0, 3, {:string_with_format, "Title", [:bold, {:align, :center}]}}]} = sheet
-
-      # Merge cells for a number
-      iex> sheet = XlsxWriter.new_sheet("Test")
-      iex> sheet = XlsxWriter.merge_range(sheet, 1, 1, 3, 1, 100)
-      iex> {"Test", [{:merge_range, 1, 1, 3, 1, {:float, 100}}]} = sheet
+      iex> sheet = XlsxWriter.new_sheet("Lists")
+      iex> sheet = XlsxWriter.hide_sheet(sheet)
+      iex> {"Lists", [:set_hidden]} = sheet
 
   """
-  def merge_range(
-        {name, instructions},
-        first_row,
-        first_col,
-        last_row,
-        last_col,
-        val,
-        opts \\ []
-      ) do
-    case Keyword.get(opts, :format) do
-      nil ->
-        {name,
-         [
-           {:merge_range, first_row, first_col, last_row, last_col,
-            to_rust_val(val)}
-           | instructions
-         ]}
-
-      formats when is_list(formats) ->
-        merge_range_with_format(
-          {name, instructions},
-          first_row,
-          first_col,
-          last_row,
-          last_col,
-          val,
-          formats
-        )
-    end
+  def hide_sheet({name, instructions}) do
+    {name, [:set_hidden | instructions]}
   end
 
-  defp merge_range_with_format(
-         sheet,
-         first_row,
-         first_col,
-         last_row,
-         last_col,
-         nil,
-         formats
-       ) do
-    merge_range_with_format(
-      sheet,
-      first_row,
-      first_col,
-      last_row,
-      last_col,
-      "",
-      formats
-    )
-  end
+  @doc """
+  Protects the sheet against changes.
 
-  defp merge_range_with_format(
-         sheet,
-         first_row,
-         first_col,
-         last_row,
-         last_col,
-         %Decimal{} = val,
-         formats
-       ) do
-    merge_range_with_format(
-      sheet,
-      first_row,
-      first_col,
-      last_row,
-      last_col,
-      Decimal.to_float(val),
-      formats
-    )
-  end
+  Once a sheet is protected, users can only edit cells formatted with
+  `:unlocked`, and the formulas of cells formatted with `:hidden` are not
+  shown. The allow flags below control what else users may do.
 
-  defp merge_range_with_format(
-         {name, instructions},
-         first_row,
-         first_col,
-         last_row,
-         last_col,
-         %Date{} = val,
-         formats
-       ) do
-    Validation.validate_formats!(formats)
+  ## Parameters
 
-    instruction =
-      {:merge_range, first_row, first_col, last_row, last_col,
-       {:date_with_format, Date.to_iso8601(val), formats}}
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `opts` - Optional keyword list:
+    - `:password` - Password required to unprotect the sheet. This only
+      deters casual changes, it is not encryption
+    - `:select_locked_cells`, `:select_unlocked_cells` - Allow selecting
+      cells (default `true`)
+    - `:format_cells`, `:format_columns`, `:format_rows` - Allow formatting
+      (default `false`)
+    - `:insert_columns`, `:insert_rows`, `:insert_links` - Allow inserting
+      (default `false`)
+    - `:delete_columns`, `:delete_rows` - Allow deleting (default `false`)
+    - `:sort`, `:autofilter`, `:pivot_tables` - Allow sorting, filtering and
+      using pivot tables (default `false`)
+    - `:edit_objects`, `:edit_scenarios` - Allow editing charts, images and
+      scenarios (default `false`)
 
-    {name, [instruction | instructions]}
-  end
+  ## Returns
 
-  defp merge_range_with_format(
-         {name, instructions},
-         first_row,
-         first_col,
-         last_row,
-         last_col,
-         %DateTime{} = val,
-         formats
-       ) do
-    Validation.validate_formats!(formats)
+  Updated sheet tuple with the protect instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Pricing")
+      iex> sheet = XlsxWriter.write(sheet, 0, 1, 100, format: [:unlocked])
+      iex> sheet = XlsxWriter.protect_sheet(sheet, password: "secret", format_columns: true)
+      iex> {"Pricing", [{:protect, [password: "secret", format_columns: true]}, _]} = sheet
+
+  """
+  def protect_sheet({name, instructions}, opts \\ []) do
+    {name, [{:protect, opts} | instructions]}
+  end
+
+  @doc """
+  Sets the print page setup of the sheet.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `opts` - Keyword list:
+    - `:orientation` - `:portrait` (default) or `:landscape`
+    - `:paper_size` - Excel paper size index, e.g. `1` for Letter, `5` for
+      Legal, `8` for A3 or `9` for A4. Defaults to the printer's paper
+    - `:margins` - Keyword list with `:left`, `:right`, `:top`, `:bottom`,
+      `:header` and `:footer` margins in inches
+    - `:center_horizontally`, `:center_vertically` - Center the printed
+      area on the page
+    - `:scale` - Print scale percentage from 10 to 400
+    - `:fit_to_pages` - `{width, height}` in pages, e.g. `{1, 0}` to fit all
+      columns on one page wide with as many pages tall as needed. Overrides
+      `:scale`
+    - `:page_order` - `:down_then_over` (default) or `:over_then_down`
+    - `:first_page_number` - Number of the first printed page
+    - `:print_gridlines` - Print the cell gridlines
+    - `:print_headings` - Print the row numbers and column letters
+    - `:black_and_white` - Print in black and white
+    - `:draft` - Print in draft quality, without most graphics
+
+  ## Returns
+
+  Updated sheet tuple with the page setup instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Report")
+      iex> sheet = XlsxWriter.set_page_setup(sheet, orientation: :landscape, paper_size: 9,
+      ...>   margins: [left: 0.5, right: 0.5], fit_to_pages: {1, 0})
+      iex> {"Report", [{:set_page_setup, [_, _, _, {:fit_to_pages, 1, 0}]}]} = sheet
+
+  """
+  def set_page_setup({name, instructions}, opts) do
+    opts =
+      Enum.map(opts, fn
+        {:fit_to_pages, {width, height}} -> {:fit_to_pages, width, height}
+        opt -> opt
+      end)
+
+    {name, [{:set_page_setup, opts} | instructions]}
+  end
+
+  @doc """
+  Sets the printed header and footer of the sheet.
+
+  Headers and footers are strings with Excel's control codes:
+
+  - `&L`, `&C`, `&R` - Start the left, center or right section
+  - `&P` - Page number, `&N` - Total number of pages
+  - `&D` - Date, `&T` - Time
+  - `&A` - Sheet name, `&F` - File name, `&Z` - File path
+  - `&G` - Image placeholder, see `:header_image` and `:footer_image`
+  - `&"Font,Style"` - Font name and style, e.g. `&"Arial,Bold Italic"`
+  - `&nn` - Font size in points, e.g. `&14`
+  - `&B`, `&I`, `&U`, `&S` - Toggle bold, italic, underline and strikethrough
+  - `&&` - A literal ampersand
+
+  Each string is limited to 255 characters once the codes are expanded.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `opts` - Keyword list:
+    - `:header`, `:footer` - Header and footer of every page, or of the odd
+      pages when even page variants are given
+    - `:first_header`, `:first_footer` - Different header and footer for the
+      first page
+    - `:even_header`, `:even_footer` - Different header and footer for even
+      pages
+    - `:header_image`, `:footer_image` - `{position, image}` where position
+      is `:left`, `:center` or `:right` and image is the binary content of
+      the image file or `{:path, path}`. The matching section of `:header` or
+      `:footer` needs a `&G` placeholder. May be given once per position
+    - `:scale_with_doc` - Scale with the page setup scale (default `true`)
+    - `:align_with_margins` - Align with the page margins (default `true`);
+      when omitted, the value from an earlier call is kept
+
+  ## Returns
+
+  Updated sheet tuple with the header/footer instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Report")
+      iex> sheet = XlsxWriter.set_header_footer(sheet,
+      ...>   header: "&L&G&C&\"Arial,Bold\"Quarterly Report&R&D",
+      ...>   footer: "&CPage &P of &N",
+      ...>   header_image: {:left, {:path, "logo.png"}})
+      iex> {"Report", [{:set_header_footer, [_, _, {:header_image_path, :left, "logo.png"}]}]} = sheet
+
+  """
+  def set_header_footer({name, instructions}, opts) do
+    opts = Enum.map(opts, &to_header_footer_option/1)
+
+    {name, [{:set_header_footer, opts} | instructions]}
+  end
+
+  defp to_header_footer_option({key, {position, image}})
+       when key in [:header_image, :footer_image] do
+    case image do
+      {:path, path} ->
+        {:"#{key}_path", position, path}
+
+      image_binary when is_binary(image_binary) ->
+        Validation.validate_image_binary!(image_binary)
+        {key, position, image_binary}
+    end
+  end
+
+  defp to_header_footer_option(opt), do: opt
+
+  @doc """
+  Sets the range of cells printed from the sheet.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `first_row` - The first row of the print area (0-based)
+  - `first_col` - The first column of the print area (0-based)
+  - `last_row` - The last row of the print area (0-based)
+  - `last_col` - The last column of the print area (0-based)
+
+  ## Returns
+
+  Updated sheet tuple with the print area instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Ledger")
+      iex> sheet = XlsxWriter.set_print_area(sheet, 0, 0, 499, 5)
+      iex> {"Ledger", [{:set_print_area, 0, 0, 499, 5}]} = sheet
+
+  """
+  def set_print_area({name, instructions}, first_row, first_col, last_row, last_col) do
+    {name, [{:set_print_area, first_row, first_col, last_row, last_col} | instructions]}
+  end
+
+  @doc """
+  Repeats a range of rows at the top of every printed page.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `first_row` - The first row to repeat (0-based)
+  - `last_row` - The last row to repeat (0-based)
+
+  ## Returns
+
+  Updated sheet tuple with the repeat rows instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Ledger")
+      iex> sheet = XlsxWriter.set_repeat_rows(sheet, 0, 1)
+      iex> {"Ledger", [{:set_repeat_rows, 0, 1}]} = sheet
+
+  """
+  def set_repeat_rows({name, instructions}, first_row, last_row) do
+    {name, [{:set_repeat_rows, first_row, last_row} | instructions]}
+  end
+
+  @doc """
+  Repeats a range of columns at the left of every printed page.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `first_col` - The first column to repeat (0-based)
+  - `last_col` - The last column to repeat (0-based)
+
+  ## Returns
+
+  Updated sheet tuple with the repeat columns instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Ledger")
+      iex> sheet = XlsxWriter.set_repeat_columns(sheet, 0, 0)
+      iex> {"Ledger", [{:set_repeat_columns, 0, 0}]} = sheet
+
+  """
+  def set_repeat_columns({name, instructions}, first_col, last_col) do
+    {name, [{:set_repeat_columns, first_col, last_col} | instructions]}
+  end
+
+  @doc """
+  Adds horizontal page breaks to the printed sheet.
+
+  Each break starts a new page above the given row. Excel allows up to 1023
+  breaks per sheet. Row 0 already starts the first page, so a break there
+  makes `generate/2` return an error.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `rows` - List of row indexes (0-based) to break before
+
+  ## Returns
+
+  Updated sheet tuple with the page breaks instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Ledger")
+      iex> sheet = XlsxWriter.set_page_breaks(sheet, [50, 100])
+      iex> {"Ledger", [{:set_page_breaks, [50, 100]}]} = sheet
+
+  """
+  def set_page_breaks({name, instructions}, rows) when is_list(rows) do
+    {name, [{:set_page_breaks, rows} | instructions]}
+  end
+
+  @doc """
+  Adds vertical page breaks to the printed sheet.
+
+  Each break starts a new page to the left of the given column. Excel allows
+  up to 1023 breaks per sheet. Column 0 already starts the first page, so a
+  break there makes `generate/2` return an error.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `cols` - List of column indexes (0-based) to break before
+
+  ## Returns
+
+  Updated sheet tuple with the vertical page breaks instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Ledger")
+      iex> sheet = XlsxWriter.set_vertical_page_breaks(sheet, [8])
+      iex> {"Ledger", [{:set_vertical_page_breaks, [8]}]} = sheet
+
+  """
+  def set_vertical_page_breaks({name, instructions}, cols) when is_list(cols) do
+    {name, [{:set_vertical_page_breaks, cols} | instructions]}
+  end
+
+  @doc """
+  Merges a range of cells into a single cell.
+
+  The merged cell will contain the specified value and formatting.
+  All merged cells will appear as one cell in Excel.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `first_row` - The first row of the merge range (0-based)
+  - `first_col` - The first column of the merge range (0-based)
+  - `last_row` - The last row of the merge range (0-based)
+  - `last_col` - The last column of the merge range (0-based)
+  - `val` - The value to write in the merged cell
+  - `opts` - Optional keyword list with:
+    - `:format` - Format specifications
+    - `:style` - Name or index of a workbook style, see `write/5`
+
+  ## Returns
+
+  Updated sheet tuple with the merge range instruction.
+
+  ## Examples
+
+      # Merge cells A1:D1 with centered title
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.merge_range(sheet, 0, 0, 0, 3, "Title", format: [:bold, {:align, :center}])
+      iex> {"Test", [{:merge_range, 0, 0, 0, 3, {:string_with_format, "Title", [:bold, {:align, :center}]}}]} = sheet
+
+      # Merge cells for a number
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.merge_range(sheet, 1, 1, 3, 1, 100)
+      iex> {"Test", [{:merge_range, 1, 1, 3, 1, {:float, 100}}]} = sheet
+
+  """
+  def merge_range(
+        {name, instructions},
+        first_row,
+        first_col,
+        last_row,
+        last_col,
+        val,
+        opts \\ []
+      ) do
+    case style_or_format(opts) do
+      {:style, style} ->
+        {name,
+         [
+           {:merge_range, first_row, first_col, last_row, last_col,
+            to_styled_rust_val(val, style)}
+           | instructions
+         ]}
+
+      {:format, nil} ->
+        {name,
+         [
+           {:merge_range, first_row, first_col, last_row, last_col,
+            to_rust_val(val)}
+           | instructions
+         ]}
+
+      {:format, formats} when is_list(formats) ->
+        merge_range_with_format(
+          {name, instructions},
+          first_row,
+          first_col,
+          last_row,
+          last_col,
+          val,
+          formats
+        )
+    end
+  end
+
+  defp merge_range_with_format(
+         sheet,
+         first_row,
+         first_col,
+         last_row,
+         last_col,
+         nil,
+         formats
+       ) do
+    merge_range_with_format(
+      sheet,
+      first_row,
+      first_col,
+      last_row,
+      last_col,
+      "",
+      formats
+    )
+  end
+
+  defp merge_range_with_format(
+         sheet,
+         first_row,
+         first_col,
+         last_row,
+         last_col,
+         %Decimal{} = val,
+         formats
+       ) do
+    merge_range_with_format(
+      sheet,
+      first_row,
+      first_col,
+      last_row,
+      last_col,
+      Decimal.to_float(val),
+      formats
+    )
+  end
+
+  defp merge_range_with_format(
+         {name, instructions},
+         first_row,
+         first_col,
+         last_row,
+         last_col,
+         %Date{} = val,
+         formats
+       ) do
+    Validation.validate_formats!(formats)
+
+    instruction =
+      {:merge_range, first_row, first_col, last_row, last_col,
+       {:date_with_format, Date.to_iso8601(val), formats}}
+
+    {name, [instruction | instructions]}
+  end
+
+  defp merge_range_with_format(
+         {name, instructions},
+         first_row,
+         first_col,
+         last_row,
+         last_col,
+         %DateTime{} = val,
+         formats
+       ) do
+    Validation.validate_formats!(formats)
 
     instruction =
       {:merge_range, first_row, first_col, last_row, last_col,
@@ -1188,6 +1824,594 @@ defmodule XlsxWriter do
           "The data type for value \"#{inspect(val)}\" is not supported."
   end
 
+  @doc """
+  Inserts a chart into the sheet, anchored at the given cell.
+
+  Series data is referenced by range rather than embedded, so the cells the
+  series point at must be written separately (on this or any other sheet).
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `row` - The anchor row index (0-based)
+  - `col` - The anchor column index (0-based)
+  - `type` - The chart type: `:area`, `:bar`, `:column`, `:line`, `:pie`,
+    `:doughnut`, `:radar`, `:scatter` and their variants (`:column_stacked`,
+    `:bar_percent_stacked`, `:scatter_smooth_with_markers`, `:radar_filled`, ...)
+  - `series` - A list of keyword lists, one per series:
+    - `:name` - The series name shown in the legend
+    - `:categories` - Category range, e.g. `"Data!$A$2:$A$7"`
+    - `:values` - Value range, e.g. `"Data!$B$2:$B$7"`
+    - `:fill_color` - Fill hex color for bars, columns, areas and pie slices
+    - `:line_color` - Line hex color for lines and borders
+    - `:line_dash` - Line dash style (see below)
+    - `:line_width` - Line width in points
+    - `:marker` - Marker options: `{:type, type}`, `{:size, 2..72}`,
+      `{:fill_color, hex}`, `{:line_color, hex}`. Marker types are `:automatic`,
+      `:none`, `:square`, `:diamond`, `:triangle`, `:x`, `:star`, `:short_dash`,
+      `:long_dash`, `:circle` and `:plus_sign`
+    - `:data_labels` - Data label options: `:value`, `:percentage`,
+      `:category_name`, `:series_name`, `:leader_lines`, `:legend_key`,
+      `{:position, pos}` and `{:num_format, format_string}`. Positions are
+      `:center`, `:right`, `:left`, `:above`, `:below`, `:inside_base`,
+      `:inside_end`, `:outside_end` and `:best_fit`
+    - `:trendline` - A trendline type, or `{type, opts}`. Types are `:linear`,
+      `:exponential`, `:logarithmic`, `:power`, `{:polynomial, 2..6}` and
+      `{:moving_average, 2..4}`. Options are `{:name, name}`, `{:line_color, hex}`,
+      `{:line_dash, style}`, `{:line_width, points}`, `:display_equation`,
+      `:display_r_squared`, `{:forward_period, n}` and `{:backward_period, n}`
+    - `:x_error_bars`, `:y_error_bars` - An error bar type, or `{type, opts}`.
+      Types are `{:fixed_value, n}`, `{:percentage, n}`, `{:standard_deviation, n}`
+      and `:standard_error`. Options are `{:direction, :both | :minus | :plus}`,
+      `{:end_cap, boolean}`, `{:line_color, hex}` and `{:line_dash, style}`.
+      Bar charts only take `:x_error_bars`, scatter charts take both and the
+      other types only take `:y_error_bars`
+    - `:secondary_axis` - Plot the series against the secondary (Y2) axis
+  - `opts` - Optional keyword list:
+    - `:title` - Chart title
+    - `:x_axis`, `:y_axis` - Keyword list of axis options (see below)
+    - `:x2_axis`, `:y2_axis` - Axis options for the secondary axes
+    - `:combine` - `{type, series}` overlaying a second chart type on this one,
+      e.g. a line over columns. Add `:secondary_axis` to its series to plot them
+      against the Y2 axis
+    - `:legend` - Legend position: `:right`, `:left`, `:top`, `:bottom`, `:top_right`
+      or `:none` to hide it
+    - `:offset` - `{x, y}` pixel offset from the anchor cell
+    - `:size` - `{width, height}` in pixels (default: 480 x 288)
+
+  ## Axis Options
+
+  `:x_axis` is the horizontal axis and `:y_axis` the vertical one, so on bar
+  charts the value axis is `:x_axis`. Scale options (`:min`, `:max`,
+  `:log_base`) only apply to value axes.
+
+  - `:title` - Axis title
+  - `:min`, `:max` - Axis bounds
+  - `:major_unit`, `:minor_unit` - Interval between major/minor ticks
+  - `:log_base` - Use a logarithmic scale with the given base (e.g. 10)
+  - `:num_format` - Number format for the axis labels, e.g. `"0%"`
+  - `:reverse` - Plot the axis in reverse order
+
+  ## Line Dash Styles
+
+  Available line dash styles: `:solid`, `:round_dot`, `:square_dot`, `:dash`,
+  `:dash_dot`, `:long_dash`, `:long_dash_dot`, `:long_dash_dot_dot`
+
+  ## Returns
+
+  Updated sheet tuple with the chart instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.insert_chart(sheet, 1, 3, :column,
+      ...>   [[name: "Sales", categories: "Test!$A$2:$A$5", values: "Test!$B$2:$B$5"]],
+      ...>   title: "Quarterly Sales", offset: {10, 5})
+      iex> {"Test", [{:insert_chart, 1, 3, :column, [[name: "Sales", categories: _, values: _]], opts}]} = sheet
+      iex> opts
+      [{:title, "Quarterly Sales"}, {:offset, 10, 5}]
+
+      # Styled series with a trendline
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.insert_chart(sheet, 1, 3, :line, [
+      ...>   [values: "Test!$B$2:$B$5", line_color: "#1F4E79", marker: [type: :circle],
+      ...>    trendline: {{:polynomial, 2}, [:display_r_squared]}]
+      ...> ])
+      iex> {"Test", [{:insert_chart, 1, 3, :line, [series], []}]} = sheet
+      iex> List.last(series)
+      {:trendline, {:polynomial, 2}, [:display_r_squared]}
+
+      # Columns with a line on the secondary axis
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.insert_chart(sheet, 1, 3, :column, [[values: "Test!$B$2:$B$5"]],
+      ...>   y2_axis: [num_format: "0%"],
+      ...>   combine: {:line, [[:secondary_axis, values: "Test!$C$2:$C$5"]]})
+      iex> {"Test", [{:insert_chart, 1, 3, :column, _, [_, combine]}]} = sheet
+      iex> combine
+      {:combine, :line, [[:secondary_axis, {:values, "Test!$C$2:$C$5"}]]}
+
+  """
+  def insert_chart({name, instructions}, row, col, type, series, opts \\ [])
+      when is_atom(type) and is_list(series) do
+    Validation.validate_cell_position!(row, col)
+
+    {name,
+     [
+       {:insert_chart, row, col, type, Enum.map(series, &to_chart_series/1),
+        to_chart_options(opts)}
+       | instructions
+     ]}
+  end
+
+  # Series options that the NIF expects as `{key, type, opts}` triples
+  @typed_series_options [:trendline, :x_error_bars, :y_error_bars]
+
+  defp to_chart_series(series_opts) do
+    Enum.map(series_opts, fn
+      {key, {type, opts}} when key in @typed_series_options and is_list(opts) ->
+        {key, type, opts}
+
+      {key, type} when key in @typed_series_options ->
+        {key, type, []}
+
+      other ->
+        other
+    end)
+  end
+
+  defp to_chart_options(opts) do
+    Enum.map(opts, fn
+      {:offset, {x, y}} -> {:offset, x, y}
+      {:size, {width, height}} -> {:size, width, height}
+      {:combine, {type, series}} -> {:combine, type, Enum.map(series, &to_chart_series/1)}
+      other -> other
+    end)
+  end
+
+  @doc """
+  Adds a sparkline, a small in-cell chart, to the given cell.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `row` - The row index of the target cell (0-based)
+  - `col` - The column index of the target cell (0-based)
+  - `range` - The data range to plot, e.g. `"Sheet1!$A$2:$F$2"`
+  - `opts` - Optional list of sparkline options:
+    - `{:type, :line | :column | :win_lose}` - Sparkline type (default: `:line`)
+    - `:high_point`, `:low_point`, `:first_point`, `:last_point`,
+      `:negative_points`, `:markers` - Highlight the given points
+    - `:axis` - Show the horizontal axis
+    - `{:color, hex}` - Sparkline color
+    - `{:high_point_color, hex}`, `{:low_point_color, hex}`,
+      `{:first_point_color, hex}`, `{:last_point_color, hex}`,
+      `{:negative_points_color, hex}`, `{:markers_color, hex}` - Point colors
+    - `{:line_weight, points}` - Line weight for line sparklines
+    - `{:min, value}`, `{:max, value}` - Custom vertical axis bounds
+    - `:group_min`, `:group_max` - Use the same bounds for every sparkline in a group
+    - `{:style, 1..36}` - One of Excel's built-in sparkline styles
+
+  ## Returns
+
+  Updated sheet tuple with the sparkline instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.add_sparkline(sheet, 1, 6, "Test!$A$2:$F$2",
+      ...>   [:high_point, :low_point, type: :column])
+      iex> {"Test", [{:add_sparkline, 1, 6, "Test!$A$2:$F$2", [:high_point, :low_point, {:type, :column}]}]} = sheet
+
+  """
+  def add_sparkline({name, instructions}, row, col, range, opts \\ [])
+      when is_binary(range) and is_list(opts) do
+    Validation.validate_cell_position!(row, col)
+
+    {name, [{:add_sparkline, row, col, range, opts} | instructions]}
+  end
+
+  @doc """
+  Adds a group of sparklines over a range of target cells.
+
+  The data range is split by row (or column) so that each target cell gets
+  its own sparkline, while the group shares a single set of options. This is
+  the equivalent of selecting several cells in Excel's sparkline dialog.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `first_row`, `first_col`, `last_row`, `last_col` - The target cell range (0-based)
+  - `range` - The 2D data range, e.g. `"Sheet1!$A$2:$F$10"` for one sparkline per row
+  - `opts` - Optional list of sparkline options (see `add_sparkline/5`)
+
+  ## Returns
+
+  Updated sheet tuple with the sparkline group instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.add_sparkline_group(sheet, 1, 6, 9, 6, "Test!$A$2:$F$10", [:group_max])
+      iex> {"Test", [{:add_sparkline_group, 1, 6, 9, 6, "Test!$A$2:$F$10", [:group_max]}]} = sheet
+
+  """
+  def add_sparkline_group(
+        {name, instructions},
+        first_row,
+        first_col,
+        last_row,
+        last_col,
+        range,
+        opts \\ []
+      )
+      when is_binary(range) and is_list(opts) do
+    Validation.validate_cell_position!(first_row, first_col)
+    Validation.validate_cell_position!(last_row, last_col)
+
+    {name,
+     [
+       {:add_sparkline_group, first_row, first_col, last_row, last_col, range, opts}
+       | instructions
+     ]}
+  end
+
+  @doc """
+  Adds a conditional format to a range of cells.
+
+  The highlight format applied when the rule matches uses the same format
+  vocabulary as `write/5`.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `first_row`, `first_col`, `last_row`, `last_col` - The cell range (0-based)
+  - `rule` - The rule to evaluate (see below)
+  - `opts` - Optional keyword list:
+    - `:format` - A list of format specifications, see `write/5`
+    - `:ranges` - Additional ranges the rule applies to, e.g. `["D2:D20"]`
+    - `:priority` - Rule priority, lower numbers are evaluated first. The
+      number is written to the file as given, across all ranges of the sheet,
+      and must be unique within the sheet. Rules without a priority come
+      after the prioritized ones, in the order added
+    - `:stop_if_true` - When `true`, lower priority rules are not evaluated
+      for cells matching this rule
+
+  ## Rules
+
+  - `{:cell, comparison}` - Compare cell values. Comparisons are
+    `{:equal_to, v}`, `{:not_equal_to, v}`, `{:greater_than, v}`,
+    `{:greater_than_or_equal_to, v}`, `{:less_than, v}`,
+    `{:less_than_or_equal_to, v}`, `{:between, min, max}` and
+    `{:not_between, min, max}`. Values are numbers or strings; strings starting
+    with `=` are formulas or cell references, e.g. `"=$H$1"`
+  - `{:text, match}` - Match text with `{:contains, text}`,
+    `{:does_not_contain, text}`, `{:begins_with, text}` or `{:ends_with, text}`
+  - `{:date, period}` - Dates occurring `:yesterday`, `:today`, `:tomorrow`,
+    `:last_seven_days`, `:last_week`, `:this_week`, `:next_week`,
+    `:last_month`, `:this_month` or `:next_month`
+  - `:blanks`, `:no_blanks`, `:errors`, `:no_errors` - Blank or error cells
+  - `:duplicate`, `:unique` - Duplicate or unique values in the range
+  - `{:top, n}`, `{:bottom, n}`, `{:top_percent, n}`, `{:bottom_percent, n}` -
+    Top or bottom ranked values
+  - `{:formula, formula}` - Match cells where the formula is true, e.g.
+    `"=$F2<TODAY()"`. References are relative to the top-left cell of the range
+  - `{:two_color_scale, opts}`, `{:three_color_scale, opts}` - Shade cells on a
+    color gradient. Options are `min:`, `mid:` and `max:` thresholds and
+    `min_color:`, `mid_color:` and `max_color:` hex colors. `mid:` and
+    `mid_color:` are only valid on three color scales
+  - `{:data_bar, opts}` - Draw a bar proportional to the cell value. Options
+    are `min:` and `max:` thresholds, `fill_color:`, `border_color:`,
+    `negative_fill_color:`, `negative_border_color:`, `axis_color:`,
+    `direction:` (`:context`, `:left_to_right`, `:right_to_left`),
+    `axis_position:` (`:automatic`, `:midpoint`, `:none`) and the flags
+    `:solid`, `:no_border` and `:bar_only`
+  - `{:icon_set, style, opts}` - Show an icon per value band. Styles are
+    `:three_arrows`, `:three_arrows_gray`, `:three_flags`,
+    `:three_traffic_lights`, `:three_traffic_lights_with_rim`, `:three_signs`,
+    `:three_symbols_circled`, `:three_symbols`, `:three_stars`,
+    `:three_triangles`, `:four_arrows`, `:four_arrows_gray`,
+    `:four_red_to_black`, `:four_ratings`, `:four_traffic_lights`,
+    `:five_arrows`, `:five_arrows_gray`, `:five_ratings`, `:five_quadrants` and
+    `:five_boxes`. Options are the flags `:reverse` and `:icons_only` and
+    `thresholds:` with one threshold per icon (the first icon always starts
+    at 0%)
+
+  Thresholds are `:automatic`, `:lowest`, `:highest`, `{:number, n}`,
+  `{:percent, n}`, `{:percentile, n}` or `{:formula, "=$H$1"}`. Color scales,
+  data bars and icon sets ignore the `:format` option.
+
+  ## Returns
+
+  Updated sheet tuple with the conditional format instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.add_conditional_format(sheet, 1, 0, 10, 0, {:cell, {:greater_than, 100}},
+      ...>   format: [{:bg_color, "#FFC7CE"}, {:font_color, "#9C0006"}])
+      iex> {"Test", [{:add_conditional_format, 1, 0, 10, 0, {:cell, {:greater_than, 100}}, [format: _]}]} = sheet
+
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.add_conditional_format(sheet, 1, 1, 10, 1,
+      ...>   {:data_bar, [:solid, fill_color: "#63BE7B", min: {:number, 0}]})
+      iex> {"Test", [{:add_conditional_format, 1, 1, 10, 1, {:data_bar, _}, []}]} = sheet
+
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.add_conditional_format(sheet, 1, 0, 20, 5, {:formula, "=$F2<TODAY()"},
+      ...>   format: [{:bg_color, "#FFC7CE"}], ranges: ["H2:J20"], priority: 1, stop_if_true: true)
+      iex> {"Test", [{:add_conditional_format, 1, 0, 20, 5, {:formula, _}, [:stop_if_true | _]}]} = sheet
+
+  """
+  def add_conditional_format(
+        {name, instructions},
+        first_row,
+        first_col,
+        last_row,
+        last_col,
+        rule,
+        opts \\ []
+      ) do
+    Validation.validate_cell_position!(first_row, first_col)
+    Validation.validate_cell_position!(last_row, last_col)
+
+    formats = Keyword.get(opts, :format)
+
+    if formats, do: Validation.validate_formats!(formats)
+
+    {stop_if_true, opts} = Keyword.pop(opts, :stop_if_true, false)
+    opts = if stop_if_true, do: [:stop_if_true | opts], else: opts
+
+    {name,
+     [
+       {:add_conditional_format, first_row, first_col, last_row, last_col, rule, opts}
+       | instructions
+     ]}
+  end
+
+  @doc """
+  Adds data validation to a range of cells.
+
+  Validated cells only accept values matching the rule, and Excel shows an
+  error alert when anything else is entered.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `first_row`, `first_col`, `last_row`, `last_col` - The cell range (0-based)
+  - `rule` - The rule to enforce (see below)
+  - `opts` - Optional keyword list:
+    - `:ignore_blank` - Allow blank cells (default `true`)
+    - `:hide_dropdown` - Hide the in-cell dropdown arrow of a list (default `false`)
+    - `:input_title`, `:input_message` - Prompt shown when the cell is selected
+    - `:error_title`, `:error_message` - Alert shown for invalid input
+    - `:error_style` - `:stop` (default, rejects the value), `:warning` or
+      `:information` (both let the user keep the value)
+
+  ## Rules
+
+  - `{:list, items}` - A dropdown with the given strings, e.g.
+    `["Open", "Closed"]`. The items joined with commas must not exceed 255
+    characters; use a range for longer lists
+  - `{:list, range}` - A dropdown with the values of a range, e.g.
+    `"=$H$1:$H$10"` or `"=Lists!$A$1:$A$20"`. The range may be on another
+    sheet, including a hidden one (see `hide_sheet/1`)
+  - `{:whole_number, criteria}`, `{:decimal, criteria}` - Numbers within the
+    criteria
+  - `{:date, criteria}`, `{:time, criteria}` - Dates or times within the
+    criteria. Bounds may be `Date`, `Time` or `NaiveDateTime` structs or
+    ISO 8601 strings
+  - `{:text_length, criteria}` - Text whose length is within the criteria
+  - `{:custom, formula}` - Values for which the formula is true, e.g.
+    `"=ISNUMBER(A2)"`
+
+  Criteria are `{:equal_to, v}`, `{:not_equal_to, v}`, `{:greater_than, v}`,
+  `{:greater_than_or_equal_to, v}`, `{:less_than, v}`,
+  `{:less_than_or_equal_to, v}`, `{:between, min, max}` and
+  `{:not_between, min, max}`. Any bound can also be a cell reference or
+  formula starting with `=`, e.g. `"=$H$1"`.
+
+  ## Returns
+
+  Updated sheet tuple with the data validation instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.add_data_validation(sheet, 1, 2, 100, 2, {:list, ["Open", "Closed"]},
+      ...>   input_title: "Status", input_message: "Pick a status")
+      iex> {"Test", [{:add_data_validation, 1, 2, 100, 2, {:list, ["Open", "Closed"]}, _}]} = sheet
+
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.add_data_validation(sheet, 1, 3, 100, 3,
+      ...>   {:date, {:between, ~D[2024-01-01], "=$H$1"}}, error_style: :warning)
+      iex> {"Test", [{:add_data_validation, 1, 3, 100, 3, {:date, {:between, "2024-01-01", "=$H$1"}}, _}]} = sheet
+
+  """
+  def add_data_validation(
+        {name, instructions},
+        first_row,
+        first_col,
+        last_row,
+        last_col,
+        rule,
+        opts \\ []
+      ) do
+    Validation.validate_cell_position!(first_row, first_col)
+    Validation.validate_cell_position!(last_row, last_col)
+
+    {name,
+     [
+       {:add_data_validation, first_row, first_col, last_row, last_col,
+        to_validation_rule(rule), opts}
+       | instructions
+     ]}
+  end
+
+  @doc """
+  Turns a range of cells into an Excel table.
+
+  Tables get filter buttons, banded styles and structured references such as
+  `Sales[Amount]`. The first row of the range is the header row, and the data
+  is written into the cells below it with `write/5` as usual.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `first_row`, `first_col`, `last_row`, `last_col` - The table range
+    including the header row (0-based)
+  - `opts` - Optional keyword list:
+    - `:name` - Table name used in structured references, e.g. `"Sales"`
+    - `:style` - `{:light, 1..21}`, `{:medium, 1..28}`, `{:dark, 1..11}` or
+      `:none` (default `{:medium, 9}`)
+    - `:header_row` - Show the header row (default `true`)
+    - `:total_row` - Use the last row of the range as a total row (default
+      `false`)
+    - `:banded_rows` - Shade alternate rows (default `true`)
+    - `:banded_columns` - Shade alternate columns (default `false`)
+    - `:first_column`, `:last_column` - Highlight the first or last column
+      (default `false`)
+    - `:autofilter` - Show filter buttons in the header (default `true`)
+    - `:columns` - One entry per column: a header name, e.g. `"Qty"`, or a
+      keyword list of column options (see below). Columns without a name are
+      called `Column1`, `Column2`, ...
+
+  ## Column Options
+
+  - `:header` - The column header name
+  - `:total_function` - Function shown in the total row: `:sum`, `:average`,
+    `:count`, `:count_numbers`, `:max`, `:min`, `:std_dev`, `:var` or
+    `{:custom, formula}`
+  - `:total_label` - Text shown in the total row instead of a function,
+    e.g. `"Total"`
+  - `:formula` - Calculated column formula using structured references, e.g.
+    `"=[@Qty]*[@Price]"`. It is written into every data row of the column
+  - `:format`, `:header_format` - Format of the column data or header cell,
+    see `write/5`
+
+  ## Returns
+
+  Updated sheet tuple with the table instruction.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.add_table(sheet, 0, 0, 10, 2,
+      ...>   name: "Sales", style: {:light, 9}, columns: ["Region", "Product", "Amount"])
+      iex> {"Test", [{:add_table, 0, 0, 10, 2, [name: "Sales", style: {:light, 9}, columns: _]}]} = sheet
+
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.add_table(sheet, 0, 0, 5, 3, total_row: true, columns: [
+      ...>   [header: "Item", total_label: "Total"],
+      ...>   [header: "Qty", total_function: :sum],
+      ...>   "Price",
+      ...>   [header: "Amount", formula: "=[@Qty]*[@Price]", total_function: :sum,
+      ...>    format: [{:num_format, "#,##0.00"}]]
+      ...> ])
+      iex> {"Test", [{:add_table, 0, 0, 5, 3, [total_row: true, columns: [_, _, [header: "Price"], _]]}]} = sheet
+
+  """
+  def add_table({name, instructions}, first_row, first_col, last_row, last_col, opts \\ []) do
+    Validation.validate_cell_position!(first_row, first_col)
+    Validation.validate_cell_position!(last_row, last_col)
+
+    {name,
+     [
+       {:add_table, first_row, first_col, last_row, last_col, to_table_options(opts)}
+       | instructions
+     ]}
+  end
+
+  @doc """
+  Writes rows of data and turns them into an Excel table in one call.
+
+  A shortcut for the common export of a header row followed by data rows.
+  The header row comes from the `:columns` option and the rows are written
+  below it with `write/4`, then `add_table/6` is called for the range they
+  cover. Use it instead of writing the headers yourself and calling
+  `set_autofilter/5` on them.
+
+  ## Parameters
+
+  - `sheet` - The sheet tuple `{name, instructions}`
+  - `first_row`, `first_col` - The top left cell of the table (0-based)
+  - `rows` - A list of rows, each a list of cell values. Calculated columns
+    (see `add_table/6`) can be left out at the end of a row, and `nil` values
+    are written as blank cells. Raises `ArgumentError` for a row with more
+    values than there are columns
+  - `opts` - The options of `add_table/6`. `:columns` is required and sets
+    the width of the table
+
+  ## Returns
+
+  Updated sheet tuple with the cell and table instructions.
+
+  ## Examples
+
+      iex> sheet = XlsxWriter.new_sheet("Test")
+      iex> sheet = XlsxWriter.write_table(sheet, 0, 0, [["North", 120], ["South", 45]],
+      ...>   name: "Sales", columns: ["Region", "Amount"])
+      iex> {"Test", [{:add_table, 0, 0, 2, 1, [name: "Sales", columns: _]} | _]} = sheet
+
+  """
+  def write_table(sheet, first_row, first_col, rows, opts \\ []) when is_list(rows) do
+    columns = Keyword.get(opts, :columns, [])
+
+    if columns == [] do
+      raise ArgumentError, "write_table/5 needs the :columns option"
+    end
+
+    data_row = if Keyword.get(opts, :header_row, true), do: first_row + 1, else: first_row
+    total_rows = if Keyword.get(opts, :total_row, false), do: 1, else: 0
+    # A table needs at least one data row, even if it's empty
+    last_row = data_row + max(length(rows), 1) - 1 + total_rows
+    last_col = first_col + length(columns) - 1
+
+    rows
+    |> Enum.with_index(data_row)
+    |> Enum.reduce(sheet, fn {values, row}, sheet ->
+      if length(values) > length(columns) do
+        raise ArgumentError,
+              "Row #{row} has #{length(values)} values but the table has #{length(columns)} columns"
+      end
+
+      values
+      |> Enum.with_index(first_col)
+      |> Enum.reduce(sheet, fn
+        {nil, col}, sheet -> write_blank(sheet, row, col)
+        {val, col}, sheet -> write(sheet, row, col, val)
+      end)
+    end)
+    |> add_table(first_row, first_col, last_row, last_col, opts)
+  end
+
+  defp to_table_options(opts) do
+    Enum.map(opts, fn
+      {:columns, columns} -> {:columns, Enum.map(columns, &to_table_column/1)}
+      opt -> opt
+    end)
+  end
+
+  defp to_table_column(header) when is_binary(header), do: [header: header]
+  defp to_table_column(column_opts) when is_list(column_opts) do
+    for key <- [:format, :header_format], formats = Keyword.get(column_opts, key) do
+      Validation.validate_formats!(formats)
+    end
+
+    column_opts
+  end
+
+  defp to_validation_rule({type, criteria}) when is_tuple(criteria) do
+    [operator | bounds] = Tuple.to_list(criteria)
+    {type, List.to_tuple([operator | Enum.map(bounds, &to_validation_bound/1)])}
+  end
+
+  defp to_validation_rule(rule), do: rule
+
+  defp to_validation_bound(%Date{} = date), do: Date.to_iso8601(date)
+  defp to_validation_bound(%Time{} = time), do: Time.to_iso8601(time)
+  defp to_validation_bound(%NaiveDateTime{} = datetime), do: NaiveDateTime.to_iso8601(datetime)
+  defp to_validation_bound(bound), do: bound
+
   defp to_rust_val(%Decimal{} = amount), do: {:float, Decimal.to_float(amount)}
   defp to_rust_val(%Date{} = date), do: {:date, Date.to_iso8601(date)}
   defp to_rust_val(%DateTime{} = datetime), do: {:date_time, DateTime.to_iso8601(datetime)}
@@ -1206,4 +2430,14 @@ defmodule XlsxWriter do
     raise XlsxWriter.Error,
           "The data type for value \"#{inspect(other)}\" is not supported."
   end
+
+  defp to_styled_rust_val(val, style) do
+    case to_rust_val(val) do
+      {:string, val} -> {:string_with_style, val, style}
+      {:float, val} -> {:number_with_style, val, style}
+      {:date, val} -> {:date_with_style, val, style}
+      {:date_time, val} -> {:date_time_with_style, val, style}
+      {:boolean, val} -> {:boolean_with_style, val, style}
+    end
+  end
 end
@@ -250,7 +250,8 @@ defmodule XlsxWriter.Builder do
   @doc """
   Generates the final Excel file and writes it to disk.
 
-  Convenience function that combines `write_binary/1` and `File.write!/2`.
+  Like `write_binary/1`, but writes the file with `XlsxWriter.generate_file/3`
+  instead of returning its content.
 
   ## Parameters
 
@@ -269,13 +270,12 @@ defmodule XlsxWriter.Builder do
 
   """
   def write_file(%__MODULE__{} = builder, path) when is_binary(path) do
-    case write_binary(builder) do
-      {:ok, content} ->
-        File.write!(path, content)
-        :ok
+    builder = finalize_current_sheet(builder)
 
-      {:error, reason} ->
-        {:error, reason}
+    if builder.sheets == [] do
+      {:error, "No sheets added. Use add_sheet/2 to add at least one sheet."}
+    else
+      XlsxWriter.generate_file(builder.sheets, path)
     end
   end
 
@@ -32,6 +32,25 @@ defmodule XlsxWriter.RustXlsxWriter do
     targets: targets,
     nif_versions: nif_versions
 
-  def write(_data), do: :erlang.nif_error(:nif_not_loaded)
-  def write_with_properties(_data, _properties), do: :erlang.nif_error(:nif_not_loaded)
+  def write(_data, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  def write_with_properties(_data, _properties, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def write_to_file(_data, _path, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  def write_to_file_with_properties(_data, _path, _properties, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def workbook_new(_memory_mode, _tempdir, _styles), do: :erlang.nif_error(:nif_not_loaded)
+
+  def workbook_add_sheet(_workbook, _name, _memory_mode), do: :erlang.nif_error(:nif_not_loaded)
+
+  def workbook_apply_instructions(_workbook, _name, _instructions),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  def workbook_finish(_workbook, _options), do: :erlang.nif_error(:nif_not_loaded)
+
+  def workbook_finish_with_properties(_workbook, _properties, _options),
+    do: :erlang.nif_error(:nif_not_loaded)
 end
@@ -223,6 +223,64 @@ defmodule XlsxWriter.Validation do
           "Rich string segments must be a list, got: #{inspect(segments)}"
   end
 
+  @doc """
+  Validates a workbook style table of `{name, formats}` tuples.
+
+  ## Parameters
+  - `styles` - The list of named styles
+
+  ## Raises
+  - `ArgumentError` if a style is not a `{name, formats}` tuple with a string name
+  - `XlsxWriter.Error` if a format option is invalid
+
+  ## Examples
+
+      iex> XlsxWriter.Validation.validate_styles!([{"header", [:bold]}])
+      :ok
+
+      iex> XlsxWriter.Validation.validate_styles!([{:header, [:bold]}])
+      ** (ArgumentError) Style must be a {name, formats} tuple with a string name, got: {:header, [:bold]}
+
+  """
+  def validate_styles!(styles) when is_list(styles) do
+    Enum.each(styles, fn
+      {name, formats} when is_binary(name) and is_list(formats) ->
+        validate_formats!(formats)
+
+      other ->
+        raise ArgumentError,
+              "Style must be a {name, formats} tuple with a string name, got: #{inspect(other)}"
+    end)
+
+    :ok
+  end
+
+  @doc """
+  Validates a reference to a workbook style, by name or by index.
+
+  ## Parameters
+  - `style` - The style name or its 0-based index in the style table
+
+  ## Raises
+  - `ArgumentError` if the reference is neither a string nor a non-negative integer
+
+  ## Examples
+
+      iex> XlsxWriter.Validation.validate_style!("currency")
+      :ok
+
+      iex> XlsxWriter.Validation.validate_style!(-1)
+      ** (ArgumentError) Style must be a name or a non-negative index, got: -1
+
+  """
+  def validate_style!(style) when is_binary(style), do: :ok
+  def validate_style!(style) when is_integer(style) and style >= 0, do: :ok
+
+  def validate_style!(style) do
+    raise ArgumentError,
+          "Style must be a name or a non-negative index, got: #{inspect(style)}"
+  end
+
   # Private helpers
 
   defp validate_color_string!(value, _field) when is_binary(value), do: :ok
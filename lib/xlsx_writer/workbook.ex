@@ -0,0 +1,188 @@
+defmodule XlsxWriter.Workbook do
+  @moduledoc """
+  Builds a workbook across several calls instead of all at once.
+
+  `XlsxWriter.generate/2` takes every sheet with all of its instructions in a
+  single call, so a large export has to be held in memory as one big list
+  first. A workbook handle keeps the workbook on the native side between
+  calls instead. Instructions can be applied in batches, e.g. from a
+  `Stream`, and each batch can be garbage collected once it is applied.
+
+  Batches are built with the usual `XlsxWriter` functions on a sheet tuple
+  named after a sheet added with `add_sheet/2`.
+
+  ## Example
+
+      workbook = XlsxWriter.Workbook.new(memory: :low)
+      :ok = XlsxWriter.Workbook.add_sheet(workbook, "Ledger")
+
+      File.stream!("ledger.csv")
+      |> Stream.with_index()
+      |> Stream.chunk_every(10_000)
+      |> Enum.each(fn chunk ->
+        batch =
+          Enum.reduce(chunk, XlsxWriter.new_sheet("Ledger"), fn {line, row}, sheet ->
+            XlsxWriter.write(sheet, row, 0, String.trim(line))
+          end)
+
+        :ok = XlsxWriter.Workbook.apply_instructions(workbook, batch)
+      end)
+
+      {:ok, content} = XlsxWriter.Workbook.finish(workbook)
+
+  ## Notes
+
+  - Chartsheets can't be built incrementally, use `XlsxWriter.generate/2`
+  - Memory modes are fixed when the workbook and its sheets are created, with
+    the `:memory` option of `new/1` and `add_sheet/3`. In constant and low
+    memory mode a batch can't change rows an earlier batch wrote out, see
+    `XlsxWriter.new_sheet/2` for what is ignored and what is rejected
+  - Conditional format `:priority` values apply across batches, a priority
+    can only be used once per sheet
+  - A workbook can only be finished once
+  """
+
+  alias XlsxWriter.RustXlsxWriter
+
+  @type t :: reference()
+
+  @doc """
+  Creates a new empty workbook.
+
+  ## Options
+
+  - `:memory` - Memory mode of the worksheets added to the workbook:
+    `:standard` (default), `:constant` or `:low`. See `XlsxWriter.new_sheet/2`
+    for how the modes keep memory use flat and what they restrict
+  - `:tempdir` - Directory for the temp files of constant and low memory
+    worksheets. Raises `ArgumentError` if it isn't a writable directory
+  - `:styles` - Named styles that batches can reference with the `:style`
+    option, see `XlsxWriter.generate/2`. They are fixed when the workbook is
+    created, so the `:styles` option of `finish/2` is ignored
+
+  ## Examples
+
+      iex> workbook = XlsxWriter.Workbook.new()
+      iex> is_reference(workbook)
+      true
+
+  """
+  @spec new(keyword()) :: t()
+  def new(opts \\ []) do
+    memory = Keyword.get(opts, :memory, :standard)
+    styles = Keyword.get(opts, :styles, [])
+    XlsxWriter.Validation.validate_styles!(styles)
+
+    case RustXlsxWriter.workbook_new(memory, Keyword.get(opts, :tempdir), styles) do
+      {:ok, workbook} -> workbook
+      {:error, reason} -> raise ArgumentError, "Invalid workbook options: #{reason}"
+    end
+  end
+
+  @doc """
+  Adds an empty worksheet to the workbook.
+
+  Sheets appear in the order they are added. Takes a `:memory` option to
+  override the memory mode given to `new/1` for this sheet.
+
+  ## Returns
+
+  - `:ok` on success
+  - `{:error, reason}` for an invalid or already added sheet name
+
+  ## Examples
+
+      iex> workbook = XlsxWriter.Workbook.new()
+      iex> XlsxWriter.Workbook.add_sheet(workbook, "Data")
+      :ok
+      iex> {:error, _reason} = XlsxWriter.Workbook.add_sheet(workbook, "Data")
+
+  """
+  @spec add_sheet(t(), String.t(), keyword()) :: :ok | {:error, String.t()}
+  def add_sheet(workbook, name, opts \\ []) when is_binary(name) do
+    case RustXlsxWriter.workbook_add_sheet(workbook, name, Keyword.get(opts, :memory)) do
+      {:ok, _} -> :ok
+      other -> other
+    end
+  end
+
+  @doc """
+  Applies a batch of instructions to a sheet of the workbook.
+
+  The batch is a sheet tuple built with `XlsxWriter.new_sheet/2` and the
+  other `XlsxWriter` functions. Its name selects the sheet added with
+  `add_sheet/2` that the instructions go to. A batch from
+  `XlsxWriter.new_sheet/2` with a `:memory` option is rejected, pass it to
+  `add_sheet/3` instead.
+
+  ## Returns
+
+  - `:ok` on success
+  - `{:error, reason}` when the sheet wasn't added, the batch sets a memory
+    mode or an instruction fails
+
+  ## Examples
+
+      iex> workbook = XlsxWriter.Workbook.new()
+      iex> :ok = XlsxWriter.Workbook.add_sheet(workbook, "Data")
+      iex> batch = XlsxWriter.new_sheet("Data") |> XlsxWriter.write(0, 0, "Hello")
+      iex> XlsxWriter.Workbook.apply_instructions(workbook, batch)
+      :ok
+
+  """
+  @spec apply_instructions(t(), {String.t(), list()}) :: :ok | {:error, String.t()}
+  def apply_instructions(workbook, {name, instructions}) do
+    case RustXlsxWriter.workbook_apply_instructions(workbook, name, Enum.reverse(instructions)) do
+      {:ok, _} -> :ok
+      other -> other
+    end
+  end
+
+  @doc """
+  Saves the workbook and returns its content.
+
+  Takes the same options as `XlsxWriter.generate/2`, except `:memory` and
+  `:tempdir`, which are given to `new/1` and return an error here. The
+  workbook can't be used after it is finished.
+
+  ## Returns
+
+  - `{:ok, xlsx_binary}` on success
+  - `{:error, reason}` on failure
+
+  ## Examples
+
+      iex> workbook = XlsxWriter.Workbook.new()
+      iex> :ok = XlsxWriter.Workbook.add_sheet(workbook, "Data")
+      iex> :ok = XlsxWriter.Workbook.apply_instructions(workbook, XlsxWriter.new_sheet("Data") |> XlsxWriter.write(0, 0, 1))
+      iex> {:ok, <<80, 75, _::binary>>} = XlsxWriter.Workbook.finish(workbook)
+      iex> {:error, _reason} = XlsxWriter.Workbook.finish(workbook)
+
+  """
+  @spec finish(t(), keyword()) :: {:ok, binary()} | {:error, String.t()}
+  def finish(workbook, opts \\ []) do
+    if Keyword.has_key?(opts, :memory) or Keyword.has_key?(opts, :tempdir) do
+      {:error, "The :memory and :tempdir options are set when the workbook is created"}
+    else
+      do_finish(workbook, opts)
+    end
+  end
+
+  defp do_finish(workbook, opts) do
+    options = XlsxWriter.WorkbookOptions.from_opts(opts)
+
+    result =
+      case Keyword.get(opts, :properties) do
+        nil ->
+          RustXlsxWriter.workbook_finish(workbook, options)
+
+        %XlsxWriter.WorkbookProperties{} = properties ->
+          RustXlsxWriter.workbook_finish_with_properties(workbook, properties, options)
+      end
+
+    case result do
+      {:ok, content} -> {:ok, IO.iodata_to_binary(content)}
+      other -> other
+    end
+  end
+end
@@ -0,0 +1,31 @@
+defmodule XlsxWriter.WorkbookOptions do
+  @moduledoc false
+
+  # This struct carries workbook-level settings from `XlsxWriter.generate/2`
+  # and `XlsxWriter.Workbook.finish/2` to the Rust NIF
+  defstruct defined_names: [],
+            protect_structure: false,
+            structure_password: nil,
+            read_only_recommended: false,
+            password: nil,
+            memory_mode: :standard,
+            tempdir: nil,
+            styles: []
+
+  def from_opts(opts) do
+    protect_workbook = Keyword.get(opts, :protect_workbook, false)
+    styles = Keyword.get(opts, :styles, [])
+    XlsxWriter.Validation.validate_styles!(styles)
+
+    %__MODULE__{
+      defined_names: Keyword.get(opts, :defined_names, []),
+      protect_structure: protect_workbook != false,
+      structure_password: if(is_list(protect_workbook), do: protect_workbook[:password]),
+      read_only_recommended: Keyword.get(opts, :read_only_recommended, false),
+      password: Keyword.get(opts, :password),
+      memory_mode: Keyword.get(opts, :memory, :standard),
+      tempdir: Keyword.get(opts, :tempdir),
+      styles: styles
+    }
+  end
+end
@@ -1,14 +1,22 @@
-use rust_xlsxwriter::{Color, DocProperties, ExcelDateTime, Format, FormatAlign, FormatBorder, FormatPattern, FormatScript, FormatUnderline, Image, Note, Workbook, Worksheet, XlsxError, Formula, Url};
-use rustler::{Binary, NifTaggedEnum};
+mod encryption;
 
-#[derive(NifTaggedEnum, PartialEq)]
+use rust_xlsxwriter::{Chart, ChartAxis, ChartDataLabel, ChartDataLabelPosition, ChartErrorBars, ChartErrorBarsDirection, ChartErrorBarsType, ChartFormat, ChartLegendPosition, ChartLine, ChartLineDashType, ChartMarker, ChartMarkerType, ChartSeries, ChartSolidFill, ChartTrendline, ChartTrendlineType, ChartType, Color, cell_range, DataValidation, DataValidationErrorStyle, DataValidationRule, ConditionalFormat2ColorScale, ConditionalFormat3ColorScale, ConditionalFormatBlank, ConditionalFormatCell, ConditionalFormatCellRule, ConditionalFormatCustomIcon, ConditionalFormatDataBar, ConditionalFormatDataBarAxisPosition, ConditionalFormatDataBarDirection, ConditionalFormatDate, ConditionalFormatDateRule, ConditionalFormatDuplicate, ConditionalFormat, ConditionalFormatError, ConditionalFormatFormula, ConditionalFormatIconSet, ConditionalFormatIconType, ConditionalFormatText, ConditionalFormatTextRule, ConditionalFormatTop, ConditionalFormatTopRule, ConditionalFormatType, ConditionalFormatValue, DocProperties, ExcelDateTime, Format, FormatAlign, FormatBorder, FormatPattern, FormatScript, FormatUnderline, HeaderImagePosition, Image, Note, ProtectionOptions, Sparkline, SparklineType, Table, TableColumn, TableFunction, TableStyle, Workbook, Worksheet, XlsxError, Formula, Url};
+use rustler::{Binary, NifTaggedEnum, NifUntaggedEnum, ResourceArc};
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read, Write};
+use std::sync::Mutex;
+use zip::result::{ZipError, ZipResult};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+#[derive(NifTaggedEnum, PartialEq, Eq, Hash)]
 enum CellAlignPos {
     Center,
     Left,
     Right,
 }
 
-#[derive(NifTaggedEnum, PartialEq)]
+#[derive(NifTaggedEnum, PartialEq, Eq, Hash)]
 enum CellVAlignPos {
     Top,
     Center,
@@ -17,7 +25,7 @@ enum CellVAlignPos {
     Distributed,
 }
 
-#[derive(NifTaggedEnum, PartialEq)]
+#[derive(NifTaggedEnum, PartialEq, Eq, Hash)]
 enum CellPattern {
     Solid,
     None,
@@ -25,7 +33,7 @@ enum CellPattern {
     Gray0625,
 }
 
-#[derive(NifTaggedEnum, PartialEq)]
+#[derive(NifTaggedEnum, PartialEq, Eq, Hash)]
 enum UnderlineStyle {
     Single,
     Double,
@@ -33,7 +41,7 @@ enum UnderlineStyle {
     DoubleAccounting,
 }
 
-#[derive(NifTaggedEnum, PartialEq)]
+#[derive(NifTaggedEnum, PartialEq, Eq, Hash)]
 enum BorderStyle {
     Thin,
     Medium,
@@ -50,7 +58,7 @@ enum BorderStyle {
     SlantDashDot,
 }
 
-#[derive(NifTaggedEnum, PartialEq)]
+#[derive(NifTaggedEnum, PartialEq, Eq, Hash)]
 enum CellFormat {
     Bold,
     Align(CellAlignPos),
@@ -81,6 +89,625 @@ enum CellFormat {
     Rotation(i16),
     Shrink,
     Indent(u8),
+    // Cell protection, only enforced once the sheet is protected
+    Locked,
+    Unlocked,
+    Hidden,
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum ChartKind {
+    Area,
+    AreaStacked,
+    AreaPercentStacked,
+    Bar,
+    BarStacked,
+    BarPercentStacked,
+    Column,
+    ColumnStacked,
+    ColumnPercentStacked,
+    Doughnut,
+    Line,
+    LineStacked,
+    LinePercentStacked,
+    Pie,
+    Radar,
+    RadarWithMarkers,
+    RadarFilled,
+    Scatter,
+    ScatterStraight,
+    ScatterStraightWithMarkers,
+    ScatterSmooth,
+    ScatterSmoothWithMarkers,
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum LegendPosition {
+    Right,
+    Left,
+    Top,
+    Bottom,
+    TopRight,
+    None,
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum LineDashStyle {
+    Solid,
+    RoundDot,
+    SquareDot,
+    Dash,
+    DashDot,
+    LongDash,
+    LongDashDot,
+    LongDashDotDot,
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum MarkerKind {
+    Automatic,
+    None,
+    Square,
+    Diamond,
+    Triangle,
+    X,
+    Star,
+    ShortDash,
+    LongDash,
+    Circle,
+    PlusSign,
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum ChartMarkerOption {
+    Type(MarkerKind),
+    Size(u8),
+    FillColor(String),
+    LineColor(String),
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum DataLabelPosition {
+    Center,
+    Right,
+    Left,
+    Above,
+    Below,
+    InsideBase,
+    InsideEnd,
+    OutsideEnd,
+    BestFit,
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum ChartDataLabelOption {
+    Value,
+    Percentage,
+    CategoryName,
+    SeriesName,
+    LeaderLines,
+    LegendKey,
+    Position(DataLabelPosition),
+    NumFormat(String),
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum TrendlineKind {
+    Linear,
+    Exponential,
+    Logarithmic,
+    Power,
+    // Order must be in Excel's range 2-6
+    Polynomial(u8),
+    // Period must be in Excel's range 2-4
+    MovingAverage(u8),
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum ChartTrendlineOption {
+    Name(String),
+    LineColor(String),
+    LineDash(LineDashStyle),
+    LineWidth(f64),
+    DisplayEquation,
+    DisplayRSquared,
+    ForwardPeriod(f64),
+    BackwardPeriod(f64),
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum ErrorBarsKind {
+    FixedValue(f64),
+    Percentage(f64),
+    StandardDeviation(f64),
+    StandardError,
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum ErrorBarsDirection {
+    Both,
+    Minus,
+    Plus,
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum ChartErrorBarsOption {
+    Direction(ErrorBarsDirection),
+    EndCap(bool),
+    LineColor(String),
+    LineDash(LineDashStyle),
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum ChartSeriesOption {
+    Name(String),
+    // Ranges use Excel's sheet reference syntax, e.g. "Sheet1!$A$2:$A$7"
+    Categories(String),
+    Values(String),
+    FillColor(String),
+    LineColor(String),
+    LineDash(LineDashStyle),
+    LineWidth(f64),
+    Marker(Vec<ChartMarkerOption>),
+    DataLabels(Vec<ChartDataLabelOption>),
+    Trendline(TrendlineKind, Vec<ChartTrendlineOption>),
+    XErrorBars(ErrorBarsKind, Vec<ChartErrorBarsOption>),
+    YErrorBars(ErrorBarsKind, Vec<ChartErrorBarsOption>),
+    SecondaryAxis,
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum ChartAxisOption {
+    Title(String),
+    Min(f64),
+    Max(f64),
+    MajorUnit(f64),
+    MinorUnit(f64),
+    LogBase(u16),
+    NumFormat(String),
+    Reverse,
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum ChartOption {
+    Title(String),
+    XAxis(Vec<ChartAxisOption>),
+    YAxis(Vec<ChartAxisOption>),
+    X2Axis(Vec<ChartAxisOption>),
+    Y2Axis(Vec<ChartAxisOption>),
+    Legend(LegendPosition),
+    Offset(u32, u32),
+    Size(u32, u32),
+    // Overlays a second chart type, e.g. a line over columns. Its series can be
+    // moved to the secondary axes with ChartSeriesOption::SecondaryAxis.
+    Combine(ChartKind, Vec<Vec<ChartSeriesOption>>),
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum SparklineKind {
+    Line,
+    Column,
+    WinLose,
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum SparklineOption {
+    Type(SparklineKind),
+    HighPoint,
+    LowPoint,
+    FirstPoint,
+    LastPoint,
+    NegativePoints,
+    Markers,
+    Axis,
+    Color(String),
+    HighPointColor(String),
+    LowPointColor(String),
+    FirstPointColor(String),
+    LastPointColor(String),
+    NegativePointsColor(String),
+    MarkersColor(String),
+    LineWeight(f64),
+    Min(f64),
+    Max(f64),
+    // Share the axis bounds across all sparklines in a group
+    GroupMin,
+    GroupMax,
+    Style(u8),
+}
+
+// Numbers are compared as numbers and strings as text, except that strings
+// starting with "=" are treated as formulas or cell references, e.g. "=$B$1".
+#[derive(NifUntaggedEnum)]
+enum ConditionalValue {
+    Number(f64),
+    Text(String),
+}
+
+#[derive(NifTaggedEnum)]
+enum CellRule {
+    EqualTo(ConditionalValue),
+    NotEqualTo(ConditionalValue),
+    GreaterThan(ConditionalValue),
+    GreaterThanOrEqualTo(ConditionalValue),
+    LessThan(ConditionalValue),
+    LessThanOrEqualTo(ConditionalValue),
+    Between(ConditionalValue, ConditionalValue),
+    NotBetween(ConditionalValue, ConditionalValue),
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum TextRule {
+    Contains(String),
+    DoesNotContain(String),
+    BeginsWith(String),
+    EndsWith(String),
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum DateRule {
+    Yesterday,
+    Today,
+    Tomorrow,
+    LastSevenDays,
+    LastWeek,
+    ThisWeek,
+    NextWeek,
+    LastMonth,
+    ThisMonth,
+    NextMonth,
+}
+
+#[derive(NifTaggedEnum)]
+enum ConditionalThreshold {
+    Automatic,
+    Lowest,
+    Highest,
+    Number(f64),
+    // Percent and percentile must be in the range 0-100
+    Percent(f64),
+    Percentile(f64),
+    Formula(String),
+}
+
+#[derive(NifTaggedEnum)]
+enum ColorScaleOption {
+    Min(ConditionalThreshold),
+    // Only used by three color scales
+    Mid(ConditionalThreshold),
+    Max(ConditionalThreshold),
+    MinColor(String),
+    MidColor(String),
+    MaxColor(String),
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum DataBarDirection {
+    Context,
+    LeftToRight,
+    RightToLeft,
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum DataBarAxisPosition {
+    Automatic,
+    Midpoint,
+    None,
+}
+
+#[derive(NifTaggedEnum)]
+enum DataBarOption {
+    Min(ConditionalThreshold),
+    Max(ConditionalThreshold),
+    FillColor(String),
+    BorderColor(String),
+    NegativeFillColor(String),
+    NegativeBorderColor(String),
+    Solid,
+    NoBorder,
+    Direction(DataBarDirection),
+    AxisPosition(DataBarAxisPosition),
+    AxisColor(String),
+    BarOnly,
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum IconStyle {
+    ThreeArrows,
+    ThreeArrowsGray,
+    ThreeFlags,
+    ThreeTrafficLights,
+    ThreeTrafficLightsWithRim,
+    ThreeSigns,
+    ThreeSymbolsCircled,
+    ThreeSymbols,
+    ThreeStars,
+    ThreeTriangles,
+    FourArrows,
+    FourArrowsGray,
+    FourRedToBlack,
+    FourRatings,
+    FourTrafficLights,
+    FiveArrows,
+    FiveArrowsGray,
+    FiveRatings,
+    FiveQuadrants,
+    FiveBoxes,
+}
+
+#[derive(NifTaggedEnum)]
+enum IconSetOption {
+    Reverse,
+    IconsOnly,
+    // One threshold per icon; the first icon always starts at 0%
+    Thresholds(Vec<ConditionalThreshold>),
+}
+
+#[derive(NifTaggedEnum)]
+enum ConditionalRule {
+    Cell(CellRule),
+    Text(TextRule),
+    Date(DateRule),
+    Blanks,
+    NoBlanks,
+    Errors,
+    NoErrors,
+    Duplicate,
+    Unique,
+    Top(u16),
+    Bottom(u16),
+    TopPercent(u16),
+    BottomPercent(u16),
+    Formula(String),
+    TwoColorScale(Vec<ColorScaleOption>),
+    ThreeColorScale(Vec<ColorScaleOption>),
+    DataBar(Vec<DataBarOption>),
+    IconSet(IconStyle, Vec<IconSetOption>),
+}
+
+#[derive(NifTaggedEnum, PartialEq)]
+enum ConditionalFormatOption {
+    Format(Vec<CellFormat>),
+    // Extra ranges the rule also applies to, e.g. "D2:D20"
+    Ranges(Vec<String>),
+    // Lower numbers are evaluated first
+    Priority(u32),
+    StopIfTrue,
+}
+
+#[derive(NifUntaggedEnum)]
+enum ListSource {
+    Items(Vec<String>),
+    // A range or formula such as "=Lists!$A$1:$A$10"
+    Range(String),
+}
+
+#[derive(NifTaggedEnum)]
+enum ValidationRule {
+    List(ListSource),
+    // Bounds are numbers, cell references/formulas starting with "=", or
+    // ISO 8601 strings for dates and times
+    WholeNumber(CellRule),
+    Decimal(CellRule),
+    Date(CellRule),
+    Time(CellRule),
+    TextLength(CellRule),
+    Custom(String),
+}
+
+#[derive(NifTaggedEnum)]
+enum ValidationErrorStyle {
+    Stop,
+    Warning,
+    Information,
+}
+
+#[derive(NifTaggedEnum)]
+enum DataValidationOption {
+    IgnoreBlank(bool),
+    HideDropdown(bool),
+    InputTitle(String),
+    InputMessage(String),
+    ErrorTitle(String),
+    ErrorMessage(String),
+    ErrorStyle(ValidationErrorStyle),
+}
+
+#[derive(NifTaggedEnum)]
+enum ProtectionOption {
+    Password(String),
+    SelectLockedCells(bool),
+    SelectUnlockedCells(bool),
+    FormatCells(bool),
+    FormatColumns(bool),
+    FormatRows(bool),
+    InsertColumns(bool),
+    InsertRows(bool),
+    InsertLinks(bool),
+    DeleteColumns(bool),
+    DeleteRows(bool),
+    Sort(bool),
+    Autofilter(bool),
+    PivotTables(bool),
+    EditScenarios(bool),
+    EditObjects(bool),
+}
+
+#[derive(NifTaggedEnum)]
+enum PageOrientation {
+    Portrait,
+    Landscape,
+}
+
+#[derive(NifTaggedEnum)]
+enum PageOrder {
+    DownThenOver,
+    OverThenDown,
+}
+
+#[derive(NifTaggedEnum)]
+enum MarginOption {
+    // Margins are in inches
+    Left(f64),
+    Right(f64),
+    Top(f64),
+    Bottom(f64),
+    Header(f64),
+    Footer(f64),
+}
+
+#[derive(NifTaggedEnum)]
+enum PageSetupOption {
+    Orientation(PageOrientation),
+    // Excel paper size index, e.g. 1 = Letter, 9 = A4
+    PaperSize(u8),
+    Margins(Vec<MarginOption>),
+    CenterHorizontally(bool),
+    CenterVertically(bool),
+    Scale(u16),
+    // Pages wide and tall, 0 leaves that direction unconstrained
+    FitToPages(u16, u16),
+    PageOrder(PageOrder),
+    FirstPageNumber(u16),
+    PrintGridlines(bool),
+    PrintHeadings(bool),
+    BlackAndWhite(bool),
+    Draft(bool),
+}
+
+#[derive(NifTaggedEnum, Clone, Copy, PartialEq)]
+enum MemoryMode {
+    Standard,
+    // Rows are written to a temp file as soon as a later row is written,
+    // strings are stored inline
+    Constant,
+    // Like constant, but keeps the shared string table in memory
+    Low,
+}
+
+#[derive(NifTaggedEnum)]
+enum OutlineOption {
+    // Excel's outline levels 1 to 7, defaults to 1
+    Level(u8),
+    Collapsed(bool),
+}
+
+#[derive(NifTaggedEnum)]
+enum SummaryRowPosition {
+    Below,
+    Above,
+}
+
+#[derive(NifTaggedEnum)]
+enum SummaryColumnPosition {
+    Right,
+    Left,
+}
+
+#[derive(NifTaggedEnum)]
+enum OutlineSummaryOption {
+    Rows(SummaryRowPosition),
+    Columns(SummaryColumnPosition),
+}
+
+// A row or column outline group, with rows and columns both held as u32.
+struct OutlineGroup {
+    first: u32,
+    last: u32,
+    level: u8,
+    collapsed: bool,
+}
+
+#[derive(NifTaggedEnum)]
+enum HeaderFooterPosition {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(NifTaggedEnum)]
+enum HeaderFooterOption<'a> {
+    Header(String),
+    Footer(String),
+    FirstHeader(String),
+    FirstFooter(String),
+    EvenHeader(String),
+    EvenFooter(String),
+    // Images go in the default header/footer and need a matching &G code
+    HeaderImage(HeaderFooterPosition, Binary<'a>),
+    HeaderImagePath(HeaderFooterPosition, String),
+    FooterImage(HeaderFooterPosition, Binary<'a>),
+    FooterImagePath(HeaderFooterPosition, String),
+    ScaleWithDoc(bool),
+    AlignWithMargins(bool),
+}
+
+// First page and even page headers/footers that rust_xlsxwriter doesn't
+// write, added to the sheet XML after saving.
+#[derive(Default)]
+struct HeaderFooterVariants {
+    first_header: Option<String>,
+    first_footer: Option<String>,
+    even_header: Option<String>,
+    even_footer: Option<String>,
+    // The last explicit :align_with_margins, restored after forcing the
+    // <headerFooter> element to be written.
+    align_with_margins: Option<bool>,
+}
+
+impl HeaderFooterVariants {
+    fn is_empty(&self) -> bool {
+        self.first_header.is_none()
+            && self.first_footer.is_none()
+            && self.even_header.is_none()
+            && self.even_footer.is_none()
+    }
+}
+
+#[derive(NifTaggedEnum)]
+enum TableStyleName {
+    None,
+    Light(u8),
+    Medium(u8),
+    Dark(u8),
+}
+
+#[derive(NifTaggedEnum)]
+enum TotalFunction {
+    Sum,
+    Average,
+    Count,
+    CountNumbers,
+    Max,
+    Min,
+    StdDev,
+    Var,
+    Custom(String),
+}
+
+#[derive(NifTaggedEnum)]
+enum TableColumnOption {
+    Header(String),
+    TotalFunction(TotalFunction),
+    TotalLabel(String),
+    // Calculated column formula, e.g. "=[@Qty]*[@Price]"
+    Formula(String),
+    Format(Vec<CellFormat>),
+    HeaderFormat(Vec<CellFormat>),
+}
+
+#[derive(NifTaggedEnum)]
+enum TableOption {
+    Name(String),
+    Style(TableStyleName),
+    HeaderRow(bool),
+    TotalRow(bool),
+    BandedRows(bool),
+    BandedColumns(bool),
+    FirstColumn(bool),
+    LastColumn(bool),
+    Autofilter(bool),
+    Columns(Vec<Vec<TableColumnOption>>),
 }
 
 #[derive(rustler::NifStruct)]
@@ -92,6 +719,13 @@ struct NoteOptions {
     height: Option<u32>,
 }
 
+// A style from the workbook style table, by name or by position in the table
+#[derive(NifUntaggedEnum)]
+enum StyleRef {
+    Index(usize),
+    Name(String),
+}
+
 #[derive(NifTaggedEnum)]
 enum CellData<'a> {
     Float(f64),
@@ -115,6 +749,13 @@ enum CellData<'a> {
     Blank(Vec<CellFormat>),
     RichString(Vec<(String, Vec<CellFormat>)>),
     RichStringWithFormat(Vec<(String, Vec<CellFormat>)>, Vec<CellFormat>),
+    StringWithStyle(String, StyleRef),
+    NumberWithStyle(f64, StyleRef),
+    DateWithStyle(String, StyleRef),
+    DateTimeWithStyle(String, StyleRef),
+    FormulaWithStyle(String, StyleRef),
+    BooleanWithStyle(bool, StyleRef),
+    BlankWithStyle(StyleRef),
 }
 
 #[derive(NifTaggedEnum)]
@@ -132,6 +773,28 @@ enum Sheet<'a> {
     InsertNote(u32, u16, String, NoteOptions),
     SetTabColor(String),
     SetAutofit,
+    InsertChart(u32, u16, ChartKind, Vec<Vec<ChartSeriesOption>>, Vec<ChartOption>),
+    // Turns the whole sheet into a chartsheet holding a single full-page chart
+    Chartsheet(ChartKind, Vec<Vec<ChartSeriesOption>>, Vec<ChartOption>),
+    AddSparkline(u32, u16, String, Vec<SparklineOption>),
+    AddSparklineGroup(u32, u16, u32, u16, String, Vec<SparklineOption>),
+    AddConditionalFormat(u32, u16, u32, u16, ConditionalRule, Vec<ConditionalFormatOption>),
+    AddDataValidation(u32, u16, u32, u16, ValidationRule, Vec<DataValidationOption>),
+    SetHidden,
+    AddTable(u32, u16, u32, u16, Vec<TableOption>),
+    Protect(Vec<ProtectionOption>),
+    SetPageSetup(Vec<PageSetupOption>),
+    SetHeaderFooter(Vec<HeaderFooterOption<'a>>),
+    SetPrintArea(u32, u16, u32, u16),
+    SetRepeatRows(u32, u32),
+    SetRepeatColumns(u16, u16),
+    SetPageBreaks(Vec<u32>),
+    SetVerticalPageBreaks(Vec<u16>),
+    GroupRows(u32, u32, Vec<OutlineOption>),
+    GroupColumns(u16, u16, Vec<OutlineOption>),
+    SetOutlineSummary(Vec<OutlineSummaryOption>),
+    // Picked up when the sheet is added, overrides the workbook memory mode
+    SetMemoryMode(MemoryMode),
 }
 
 #[derive(rustler::NifStruct)]
@@ -148,171 +811,2316 @@ struct WorkbookProperties {
     status: Option<String>,
 }
 
-#[rustler::nif(schedule = "DirtyCpu")]
-fn write(sheets: Vec<(String, Vec<Sheet>)>) -> Result<Vec<u8>, String> {
-    write_impl(sheets, None)
+#[derive(rustler::NifStruct)]
+#[module = "XlsxWriter.WorkbookOptions"]
+struct WorkbookOptions {
+    // (name, formula) pairs; names like "Sheet1!Name" are scoped to that sheet
+    defined_names: Vec<(String, String)>,
+    protect_structure: bool,
+    structure_password: Option<String>,
+    read_only_recommended: bool,
+    // Password required to open the file, the package is encrypted when set
+    password: Option<String>,
+    // Default for the worksheets, used when the workbook is created
+    memory_mode: MemoryMode,
+    tempdir: Option<String>,
+    // Named format lists that cells can reference instead of their own list
+    styles: Vec<(String, Vec<CellFormat>)>,
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn write(sheets: Vec<(String, Vec<Sheet>)>, options: WorkbookOptions) -> Result<Vec<u8>, String> {
+    write_impl(sheets, None, options)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn write_with_properties(
+    sheets: Vec<(String, Vec<Sheet>)>,
+    properties: WorkbookProperties,
+    options: WorkbookOptions,
+) -> Result<Vec<u8>, String> {
+    write_impl(sheets, Some(properties), options)
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn write_to_file(sheets: Vec<(String, Vec<Sheet>)>, path: String, options: WorkbookOptions) -> Result<(), String> {
+    write_to_file_impl(sheets, &path, None, options)
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn write_to_file_with_properties(
+    sheets: Vec<(String, Vec<Sheet>)>,
+    path: String,
+    properties: WorkbookProperties,
+    options: WorkbookOptions,
+) -> Result<(), String> {
+    write_to_file_impl(sheets, &path, Some(properties), options)
+}
+
+fn write_impl(
+    sheets: Vec<(String, Vec<Sheet>)>,
+    properties: Option<WorkbookProperties>,
+    mut options: WorkbookOptions,
+) -> Result<Vec<u8>, String> {
+    build_workbook(sheets, &mut options)?.finish(properties, options)
+}
+
+fn write_to_file_impl(
+    sheets: Vec<(String, Vec<Sheet>)>,
+    path: &str,
+    properties: Option<WorkbookProperties>,
+    mut options: WorkbookOptions,
+) -> Result<(), String> {
+    build_workbook(sheets, &mut options)?.finish_to_file(properties, options, path)
+}
+
+fn build_workbook(sheets: Vec<(String, Vec<Sheet>)>, options: &mut WorkbookOptions) -> Result<WorkbookBuilder, String> {
+    let styles = std::mem::take(&mut options.styles);
+    let mut builder = WorkbookBuilder::new(options.memory_mode, options.tempdir.as_deref(), styles)?;
+
+    for (sheet_name, sheet) in sheets {
+        let is_chartsheet = sheet
+            .iter()
+            .any(|instruction| matches!(instruction, Sheet::Chartsheet(..)));
+        let memory_mode = sheet.iter().find_map(|instruction| match instruction {
+            Sheet::SetMemoryMode(mode) => Some(*mode),
+            _ => None,
+        });
+
+        let index = builder.add_sheet(sheet_name, is_chartsheet, memory_mode)?;
+        builder.apply_instructions(index, sheet)?;
+    }
+
+    Ok(builder)
+}
+
+// A workbook kept between NIF calls so sheets can be filled in batches instead
+// of passing every instruction to a single write call. The builder is taken
+// out when the workbook is finished.
+struct WorkbookResource {
+    builder: Mutex<Option<WorkbookBuilder>>,
+}
+
+#[rustler::resource_impl]
+impl rustler::Resource for WorkbookResource {}
+
+impl WorkbookResource {
+    fn with_builder<T>(&self, f: impl FnOnce(&mut WorkbookBuilder) -> Result<T, String>) -> Result<T, String> {
+        let mut builder = match self.builder.lock() {
+            Ok(builder) => builder,
+            Err(_) => return Err("Workbook is unusable after a crash in an earlier call".to_string()),
+        };
+
+        match builder.as_mut() {
+            Some(builder) => f(builder),
+            None => Err("Workbook has already been finished".to_string()),
+        }
+    }
+
+    fn take_builder(&self) -> Result<WorkbookBuilder, String> {
+        match self.builder.lock() {
+            Ok(mut builder) => builder.take().ok_or_else(|| "Workbook has already been finished".to_string()),
+            Err(_) => Err("Workbook is unusable after a crash in an earlier call".to_string()),
+        }
+    }
+}
+
+#[rustler::nif]
+fn workbook_new(
+    memory_mode: MemoryMode,
+    tempdir: Option<String>,
+    styles: Vec<(String, Vec<CellFormat>)>,
+) -> Result<ResourceArc<WorkbookResource>, String> {
+    let builder = WorkbookBuilder::new(memory_mode, tempdir.as_deref(), styles)?;
+
+    Ok(ResourceArc::new(WorkbookResource {
+        builder: Mutex::new(Some(builder)),
+    }))
+}
+
+#[rustler::nif]
+fn workbook_add_sheet(
+    resource: ResourceArc<WorkbookResource>,
+    name: String,
+    memory_mode: Option<MemoryMode>,
+) -> Result<(), String> {
+    resource.with_builder(|builder| {
+        if builder.sheet_index(&name).is_some() {
+            return Err(format!("Sheet '{name}' has already been added"));
+        }
+
+        builder.add_sheet(name, false, memory_mode).map(|_| ())
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn workbook_apply_instructions(
+    resource: ResourceArc<WorkbookResource>,
+    name: String,
+    sheet: Vec<Sheet>,
+) -> Result<(), String> {
+    if sheet.iter().any(|instruction| matches!(instruction, Sheet::Chartsheet(..))) {
+        return Err("Chartsheets can't be added to a workbook incrementally".to_string());
+    }
+    if sheet.iter().any(|instruction| matches!(instruction, Sheet::SetMemoryMode(_))) {
+        return Err("The memory mode of a sheet is set by the :memory option when it is added".to_string());
+    }
+
+    resource.with_builder(|builder| match builder.sheet_index(&name) {
+        Some(index) => builder.apply_instructions(index, sheet),
+        None => Err(format!("Sheet '{name}' has not been added")),
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn workbook_finish(resource: ResourceArc<WorkbookResource>, options: WorkbookOptions) -> Result<Vec<u8>, String> {
+    resource.take_builder()?.finish(None, options)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn workbook_finish_with_properties(
+    resource: ResourceArc<WorkbookResource>,
+    properties: WorkbookProperties,
+    options: WorkbookOptions,
+) -> Result<Vec<u8>, String> {
+    resource.take_builder()?.finish(Some(properties), options)
+}
+
+// State of a sheet that is only applied when the workbook is saved.
+struct SheetState {
+    name: String,
+    // Path of the sheet XML in the saved package
+    path: String,
+    header_footer_variants: HeaderFooterVariants,
+    // Constant and low memory sheets write out rows as they go
+    flushes_rows: bool,
+    row_groups: Vec<OutlineGroup>,
+    column_groups: Vec<OutlineGroup>,
+    conditional_formats: Vec<ConditionalFormatRule>,
+}
+
+// A conditional format as rust_xlsxwriter stores it, to work out the
+// priority it gave the rule when the sheet is saved.
+struct ConditionalFormatRule {
+    range: String,
+    priority: Option<u32>,
+    x14: bool,
+}
+
+// Rewrites of the saved package, by sheet XML path.
+#[derive(Default)]
+struct PackageRewrites {
+    header_footer_variants: HashMap<String, HeaderFooterVariants>,
+    conditional_format_priorities: HashMap<String, ConditionalFormatPriorities>,
+}
+
+// Priority written by rust_xlsxwriter to the requested priority, for the
+// <cfRule> and the <x14:cfRule> elements which it numbers differently.
+struct ConditionalFormatPriorities {
+    classic: HashMap<u32, u32>,
+    x14: HashMap<u32, u32>,
+}
+
+impl PackageRewrites {
+    fn is_empty(&self) -> bool {
+        self.header_footer_variants.is_empty() && self.conditional_format_priorities.is_empty()
+    }
+}
+
+// A workbook being built, either in a single write call or across the calls
+// made on a WorkbookResource.
+struct WorkbookBuilder {
+    workbook: Workbook,
+    sheets: Vec<SheetState>,
+    memory_mode: MemoryMode,
+    // Sheet XML paths are numbered separately for worksheets and chartsheets
+    worksheet_count: usize,
+    chartsheet_count: usize,
+    formats: FormatCache,
+}
+
+impl WorkbookBuilder {
+    fn new(
+        memory_mode: MemoryMode,
+        tempdir: Option<&str>,
+        styles: Vec<(String, Vec<CellFormat>)>,
+    ) -> Result<Self, String> {
+        let mut workbook = Workbook::new();
+
+        if let Some(tempdir) = tempdir {
+            if let Err(e) = workbook.set_tempdir(tempdir) {
+                return Err(e.to_string());
+            }
+        }
+
+        Ok(WorkbookBuilder {
+            workbook,
+            sheets: Vec::new(),
+            memory_mode,
+            worksheet_count: 0,
+            chartsheet_count: 0,
+            formats: FormatCache::new(styles)?,
+        })
+    }
+
+    fn add_sheet(&mut self, name: String, is_chartsheet: bool, memory_mode: Option<MemoryMode>) -> Result<usize, String> {
+        let memory_mode = if is_chartsheet {
+            MemoryMode::Standard
+        } else {
+            memory_mode.unwrap_or(self.memory_mode)
+        };
+
+        let (worksheet, path) = if is_chartsheet {
+            self.chartsheet_count += 1;
+            (self.workbook.add_chartsheet(), format!("xl/chartsheets/sheet{}.xml", self.chartsheet_count))
+        } else {
+            self.worksheet_count += 1;
+            let worksheet = match memory_mode {
+                MemoryMode::Standard => self.workbook.add_worksheet(),
+                MemoryMode::Constant => self.workbook.add_worksheet_with_constant_memory(),
+                MemoryMode::Low => self.workbook.add_worksheet_with_low_memory(),
+            };
+            (worksheet, format!("xl/worksheets/sheet{}.xml", self.worksheet_count))
+        };
+
+        if let Err(e) = worksheet.set_name(&name) {
+            return Err(e.to_string());
+        }
+
+        self.sheets.push(SheetState {
+            name,
+            path,
+            header_footer_variants: HeaderFooterVariants::default(),
+            flushes_rows: memory_mode != MemoryMode::Standard,
+            row_groups: Vec::new(),
+            column_groups: Vec::new(),
+            conditional_formats: Vec::new(),
+        });
+
+        Ok(self.sheets.len() - 1)
+    }
+
+    fn sheet_index(&self, name: &str) -> Option<usize> {
+        self.sheets.iter().position(|state| state.name == name)
+    }
+
+    fn apply_instructions(&mut self, index: usize, sheet: Vec<Sheet>) -> Result<(), String> {
+        let state = &mut self.sheets[index];
+        let format_cache = &mut self.formats;
+        let mut worksheet = match self.workbook.worksheet_from_index(index) {
+            Ok(ws) => ws,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        for instruction in sheet {
+            worksheet = match instruction {
+                Sheet::SetColumnWidth(col, val) => match worksheet.set_column_width(col, val) {
+                    Ok(ws) => ws,
+                    Err(e) => return Err(e.to_string()),
+                },
+                Sheet::SetRowHeight(row, val) => match worksheet.set_row_height(row, val) {
+                    Ok(ws) => ws,
+                    Err(e) => return Err(e.to_string()),
+                },
+                Sheet::SetColumnRangeWidth(first_col, last_col, width) => {
+                    let mut ws = worksheet;
+                    for col in first_col..=last_col {
+                        ws = match ws.set_column_width(col, width) {
+                            Ok(w) => w,
+                            Err(e) => return Err(e.to_string()),
+                        };
+                    }
+                    ws
+                }
+                Sheet::SetRowRangeHeight(first_row, last_row, height) => {
+                    let mut ws = worksheet;
+                    for row in first_row..=last_row {
+                        ws = match ws.set_row_height(row, height) {
+                            Ok(w) => w,
+                            Err(e) => return Err(e.to_string()),
+                        };
+                    }
+                    ws
+                }
+                Sheet::SetFreezePanes(row, col) => match worksheet.set_freeze_panes(row, col) {
+                    Ok(ws) => ws,
+                    Err(e) => return Err(e.to_string()),
+                },
+                Sheet::SetRowHidden(row) => match worksheet.set_row_hidden(row) {
+                    Ok(ws) => ws,
+                    Err(e) => return Err(e.to_string()),
+                },
+                Sheet::SetColumnHidden(col) => match worksheet.set_column_hidden(col) {
+                    Ok(ws) => ws,
+                    Err(e) => return Err(e.to_string()),
+                },
+                Sheet::SetAutofilter(first_row, first_col, last_row, last_col) => {
+                    match worksheet.autofilter(first_row, first_col, last_row, last_col) {
+                        Ok(ws) => ws,
+                        Err(e) => return Err(e.to_string()),
+                    }
+                }
+                Sheet::MergeRange(first_row, first_col, last_row, last_col, data) => {
+                    match merge_range(worksheet, format_cache, first_row, first_col, last_row, last_col, data) {
+                        Ok(ws) => ws,
+                        Err(e) => return Err(e.to_string()),
+                    }
+                }
+                Sheet::InsertNote(row, col, text, options) => {
+                    match insert_note(worksheet, row, col, text, options) {
+                        Ok(ws) => ws,
+                        Err(e) => return Err(e.to_string()),
+                    }
+                }
+                Sheet::SetTabColor(color_hex) => {
+                    if let Some(color) = parse_hex_color(&color_hex) {
+                        worksheet.set_tab_color(color)
+                    } else {
+                        worksheet
+                    }
+                }
+                Sheet::SetAutofit => worksheet.autofit(),
+                Sheet::InsertChart(row, col, kind, series, options) => {
+                    match insert_chart(worksheet, row, col, kind, series, options) {
+                        Ok(ws) => ws,
+                        Err(e) => return Err(e.to_string()),
+                    }
+                }
+                Sheet::Chartsheet(kind, series, options) => {
+                    match insert_chart(worksheet, 0, 0, kind, series, options) {
+                        Ok(ws) => ws,
+                        Err(e) => return Err(e.to_string()),
+                    }
+                }
+                Sheet::AddSparkline(row, col, range, options) => {
+                    let sparkline = build_sparkline(&range, &options);
+                    match worksheet.add_sparkline(row, col, &sparkline) {
+                        Ok(ws) => ws,
+                        Err(e) => return Err(e.to_string()),
+                    }
+                }
+                Sheet::AddConditionalFormat(first_row, first_col, last_row, last_col, rule, options) => {
+                    match add_conditional_format(
+                        worksheet,
+                        format_cache,
+                        &mut state.conditional_formats,
+                        first_row,
+                        first_col,
+                        last_row,
+                        last_col,
+                        rule,
+                        options,
+                    ) {
+                        Ok(ws) => ws,
+                        Err(e) => return Err(e.to_string()),
+                    }
+                }
+                Sheet::AddDataValidation(first_row, first_col, last_row, last_col, rule, options) => {
+                    match add_data_validation(worksheet, first_row, first_col, last_row, last_col, rule, options) {
+                        Ok(ws) => ws,
+                        Err(e) => return Err(e.to_string()),
+                    }
+                }
+                Sheet::SetHidden => worksheet.set_hidden(true),
+                Sheet::Protect(options) => protect_sheet(worksheet, options),
+                Sheet::SetPageSetup(options) => match set_page_setup(worksheet, options) {
+                    Ok(ws) => ws,
+                    Err(e) => return Err(e.to_string()),
+                },
+                Sheet::SetHeaderFooter(options) => match set_header_footer(worksheet, options, &mut state.header_footer_variants) {
+                    Ok(ws) => ws,
+                    Err(e) => return Err(e.to_string()),
+                },
+                // Outline levels depend on all the groups of the sheet, so
+                // they are collected here and applied when the workbook is saved
+                Sheet::GroupRows(first_row, last_row, options) => {
+                    // rust_xlsxwriter doesn't write outline levels for rows it
+                    // has flushed to the temp file
+                    if state.flushes_rows {
+                        return Err("Row outline groups aren't supported in constant or low memory mode".to_string());
+                    }
+
+                    match outline_group(first_row, last_row, options) {
+                        Ok(group) => state.row_groups.push(group),
+                        Err(e) => return Err(e.to_string()),
+                    }
+                    worksheet
+                }
+                Sheet::GroupColumns(first_col, last_col, options) => {
+                    match outline_group(first_col.into(), last_col.into(), options) {
+                        Ok(group) => state.column_groups.push(group),
+                        Err(e) => return Err(e.to_string()),
+                    }
+                    worksheet
+                }
+                Sheet::SetOutlineSummary(options) => set_outline_summary(worksheet, options),
+                Sheet::SetMemoryMode(_) => worksheet,
+                Sheet::SetPrintArea(first_row, first_col, last_row, last_col) => {
+                    match worksheet.set_print_area(first_row, first_col, last_row, last_col) {
+                        Ok(ws) => ws,
+                        Err(e) => return Err(e.to_string()),
+                    }
+                }
+                Sheet::SetRepeatRows(first_row, last_row) => match worksheet.set_repeat_rows(first_row, last_row) {
+                    Ok(ws) => ws,
+                    Err(e) => return Err(e.to_string()),
+                },
+                Sheet::SetRepeatColumns(first_col, last_col) => {
+                    match worksheet.set_repeat_columns(first_col, last_col) {
+                        Ok(ws) => ws,
+                        Err(e) => return Err(e.to_string()),
+                    }
+                }
+                // A break before the first row/column is meaningless, and
+                // rust_xlsxwriter panics when it is the only one given
+                Sheet::SetPageBreaks(rows) => {
+                    // The first page already starts at row 0
+                    if rows.contains(&0) {
+                        return Err("Page breaks must come after row 0".to_string());
+                    }
+                    match worksheet.set_page_breaks(&rows) {
+                        Ok(ws) => ws,
+                        Err(e) => return Err(e.to_string()),
+                    }
+                }
+                Sheet::SetVerticalPageBreaks(cols) => {
+                    if cols.contains(&0) {
+                        return Err("Vertical page breaks must come after column 0".to_string());
+                    }
+                    let cols: Vec<u32> = cols.into_iter().map(u32::from).collect();
+                    match worksheet.set_vertical_page_breaks(&cols) {
+                        Ok(ws) => ws,
+                        Err(e) => return Err(e.to_string()),
+                    }
+                }
+                Sheet::AddTable(first_row, first_col, last_row, last_col, options) => {
+                    match add_table(worksheet, format_cache, first_row, first_col, last_row, last_col, options) {
+                        Ok(ws) => ws,
+                        Err(e) => return Err(e.to_string()),
+                    }
+                }
+                Sheet::AddSparklineGroup(first_row, first_col, last_row, last_col, range, options) => {
+                    let sparkline = build_sparkline(&range, &options);
+                    match worksheet.add_sparkline_group(first_row, first_col, last_row, last_col, &sparkline) {
+                        Ok(ws) => ws,
+                        Err(e) => return Err(e.to_string()),
+                    }
+                }
+                Sheet::Write(row, col, data) => match write_data(worksheet, format_cache, row, col, data) {
+                    Ok(ws) => ws,
+                    Err(e) => return Err(e.to_string()),
+                },
+            };
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self, properties: Option<WorkbookProperties>, options: WorkbookOptions) -> Result<Vec<u8>, String> {
+        let rewrites = self.prepare_save(properties, &options)?;
+
+        let buf = match self.workbook.save_to_buffer() {
+            Ok(buf) => buf,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        post_process_package(buf, &rewrites, options)
+    }
+
+    // Saves straight to the file unless the package has to be rewritten
+    // afterwards, which needs it in memory anyway.
+    fn finish_to_file(
+        mut self,
+        properties: Option<WorkbookProperties>,
+        options: WorkbookOptions,
+        path: &str,
+    ) -> Result<(), String> {
+        let rewrites = self.prepare_save(properties, &options)?;
+
+        if rewrites.is_empty() && !options.protect_structure && options.password.is_none() {
+            return match self.workbook.save(path) {
+                Ok(()) => Ok(()),
+                Err(e) => Err(e.to_string()),
+            };
+        }
+
+        let buf = match self.workbook.save_to_buffer() {
+            Ok(buf) => buf,
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let buf = post_process_package(buf, &rewrites, options)?;
+
+        match std::fs::write(path, buf) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    // Applies the workbook-wide settings and outline groups, and returns what
+    // has to be rewritten in the saved package.
+    fn prepare_save(
+        &mut self,
+        properties: Option<WorkbookProperties>,
+        options: &WorkbookOptions,
+    ) -> Result<PackageRewrites, String> {
+        if let Some(props) = properties {
+            let mut doc_props = DocProperties::new();
+            if let Some(author) = props.author {
+                doc_props = doc_props.set_author(&author);
+            }
+            if let Some(title) = props.title {
+                doc_props = doc_props.set_title(&title);
+            }
+            if let Some(subject) = props.subject {
+                doc_props = doc_props.set_subject(&subject);
+            }
+            if let Some(manager) = props.manager {
+                doc_props = doc_props.set_manager(&manager);
+            }
+            if let Some(company) = props.company {
+                doc_props = doc_props.set_company(&company);
+            }
+            if let Some(category) = props.category {
+                doc_props = doc_props.set_category(&category);
+            }
+            if let Some(keywords) = props.keywords {
+                doc_props = doc_props.set_keywords(&keywords);
+            }
+            if let Some(comment) = props.comment {
+                doc_props = doc_props.set_comment(&comment);
+            }
+            if let Some(status) = props.status {
+                doc_props = doc_props.set_status(&status);
+            }
+            self.workbook.set_properties(&doc_props);
+        }
+
+        for (index, state) in self.sheets.iter().enumerate() {
+            let worksheet = match self.workbook.worksheet_from_index(index) {
+                Ok(ws) => ws,
+                Err(e) => return Err(e.to_string()),
+            };
+
+            if let Err(e) = group_rows(worksheet, &state.row_groups) {
+                return Err(e.to_string());
+            }
+
+            if let Err(e) = group_columns(worksheet, &state.column_groups) {
+                return Err(e.to_string());
+            }
+        }
+
+        for (name, formula) in &options.defined_names {
+            if name.is_empty() || name.ends_with('!') {
+                return Err(format!("Defined name '{name}' must not be empty"));
+            }
+
+            if let Err(e) = self.workbook.define_name(name, formula) {
+                return Err(e.to_string());
+            }
+        }
+
+        if options.read_only_recommended {
+            self.workbook.read_only_recommended();
+        }
+
+        let mut rewrites = PackageRewrites::default();
+        for state in std::mem::take(&mut self.sheets) {
+            if let Some(priorities) = conditional_format_priorities(&state.conditional_formats)? {
+                rewrites.conditional_format_priorities.insert(state.path.clone(), priorities);
+            }
+
+            if !state.header_footer_variants.is_empty() {
+                rewrites.header_footer_variants.insert(state.path, state.header_footer_variants);
+            }
+        }
+
+        Ok(rewrites)
+    }
+}
+
+// rust_xlsxwriter 0.95 numbers conditional formats grouped by range, the ranges
+// in the order they were first used, so map its numbers to the requested ones.
+// This copies private numbering rules, which is why the crate is pinned to an
+// exact version and the mapping is covered by a test on the written XML.
+// Rules without a priority come after the others, in the order added. The
+// x14 rules are numbered over the ranges in sorted order, counting only
+// ranges that hold one.
+fn conditional_format_priorities(
+    rules: &[ConditionalFormatRule],
+) -> Result<Option<ConditionalFormatPriorities>, String> {
+    if rules.iter().all(|rule| rule.priority.is_none()) {
+        return Ok(None);
+    }
+
+    let mut requested = HashMap::new();
+    for rule in rules {
+        if let Some(priority) = rule.priority {
+            if requested.insert(priority, ()).is_some() {
+                return Err(format!("Conditional format priority {priority} is used more than once"));
+            }
+        }
+    }
+
+    let mut next = requested.keys().max().copied().unwrap_or(0);
+    let priorities: Vec<u32> = rules
+        .iter()
+        .map(|rule| {
+            rule.priority.unwrap_or_else(|| {
+                next += 1;
+                next
+            })
+        })
+        .collect();
+
+    let mut ranges: Vec<&str> = Vec::new();
+    for rule in rules {
+        if !ranges.contains(&rule.range.as_str()) {
+            ranges.push(&rule.range);
+        }
+    }
+
+    let mut classic = HashMap::new();
+    for range in &ranges {
+        for (index, _) in rules.iter().enumerate().filter(|(_, rule)| rule.range == *range) {
+            classic.insert(classic.len() as u32 + 1, priorities[index]);
+        }
+    }
+
+    ranges.sort_unstable();
+    let mut x14 = HashMap::new();
+    let mut written = 0;
+    for range in &ranges {
+        let range_rules = || rules.iter().enumerate().filter(|(_, rule)| rule.range == *range);
+        if !range_rules().any(|(_, rule)| rule.x14) {
+            continue;
+        }
+
+        for (index, _) in range_rules() {
+            written += 1;
+            x14.insert(written, priorities[index]);
+        }
+    }
+
+    Ok(Some(ConditionalFormatPriorities { classic, x14 }))
+}
+
+// Adds what rust_xlsxwriter can't write itself to the saved package, then
+// encrypts it if a password is set.
+fn post_process_package(buf: Vec<u8>, rewrites: &PackageRewrites, options: WorkbookOptions) -> Result<Vec<u8>, String> {
+    let buf = if rewrites.header_footer_variants.is_empty() {
+        buf
+    } else {
+        match add_header_footer_variants(buf, &rewrites.header_footer_variants) {
+            Ok(buf) => buf,
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+
+    let buf = if rewrites.conditional_format_priorities.is_empty() {
+        buf
+    } else {
+        match set_conditional_format_priorities(buf, &rewrites.conditional_format_priorities) {
+            Ok(buf) => buf,
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+
+    let buf = if options.protect_structure {
+        match protect_workbook_structure(buf, options.structure_password.as_deref()) {
+            Ok(buf) => buf,
+            Err(e) => return Err(e.to_string()),
+        }
+    } else {
+        buf
+    };
+
+    match options.password {
+        Some(password) => match encryption::encrypt_package(&buf, &password) {
+            Ok(buf) => Ok(buf),
+            Err(e) => Err(e.to_string()),
+        },
+        None => Ok(buf),
+    }
+}
+
+// rust_xlsxwriter doesn't support workbook protection, so add the
+// <workbookProtection> element to xl/workbook.xml of the saved package.
+fn protect_workbook_structure(buf: Vec<u8>, password: Option<&str>) -> ZipResult<Vec<u8>> {
+    let protection = match password {
+        Some(password) => format!(
+            r#"<workbookProtection workbookPassword="{:04X}" lockStructure="1"/>"#,
+            hash_password(password)
+        ),
+        None => r#"<workbookProtection lockStructure="1"/>"#.to_string(),
+    };
+
+    rewrite_package(buf, &["xl/workbook.xml"], |_, xml| {
+        Ok(xml.replacen("<bookViews>", &format!("{protection}<bookViews>"), 1))
+    })
+}
+
+// Add the first page and even page variants to the <headerFooter> element of
+// each sheet, after the odd page ones rust_xlsxwriter wrote.
+fn add_header_footer_variants(buf: Vec<u8>, variants: &HashMap<String, HeaderFooterVariants>) -> ZipResult<Vec<u8>> {
+    let paths: Vec<&str> = variants.keys().map(String::as_str).collect();
+
+    rewrite_package(buf, &paths, |path, xml| {
+        let variants = &variants[path];
+        let Some(start) = xml.find("<headerFooter") else {
+            return Err(malformed_part(path, "missing headerFooter element"));
+        };
+        let Some(tag_length) = xml[start..].find('>') else {
+            return Err(malformed_part(path, "unterminated headerFooter element"));
+        };
+        let tag_end = start + tag_length;
+
+        let mut attributes = String::new();
+        if variants.even_header.is_some() || variants.even_footer.is_some() {
+            attributes.push_str(r#" differentOddEven="1""#);
+        }
+        if variants.first_header.is_some() || variants.first_footer.is_some() {
+            attributes.push_str(r#" differentFirst="1""#);
+        }
+
+        let mut elements = String::new();
+        for (tag, value) in [
+            ("evenHeader", &variants.even_header),
+            ("evenFooter", &variants.even_footer),
+            ("firstHeader", &variants.first_header),
+            ("firstFooter", &variants.first_footer),
+        ] {
+            if let Some(value) = value {
+                elements.push_str(&format!("<{tag}>{}</{tag}>", escape_xml(&expand_header_footer_codes(value))));
+            }
+        }
+
+        // The element is either empty, <headerFooter .../>, or holds the odd
+        // page header/footer which have to come first.
+        let open_tag = &xml[start + "<headerFooter".len()..tag_end];
+        let (existing_attributes, content, end) = match open_tag.strip_suffix('/') {
+            Some(existing_attributes) => (existing_attributes, "", tag_end + 1),
+            None => {
+                let Some(close) = xml[tag_end..].find("</headerFooter>").map(|close| tag_end + close) else {
+                    return Err(malformed_part(path, "unclosed headerFooter element"));
+                };
+                (open_tag, &xml[tag_end + 1..close], close + "</headerFooter>".len())
+            }
+        };
+
+        Ok(format!(
+            "{}<headerFooter{attributes}{existing_attributes}>{content}{elements}</headerFooter>{}",
+            &xml[..start],
+            &xml[end..]
+        ))
+    })
+}
+
+fn set_conditional_format_priorities(
+    buf: Vec<u8>,
+    priorities: &HashMap<String, ConditionalFormatPriorities>,
+) -> ZipResult<Vec<u8>> {
+    let paths: Vec<&str> = priorities.keys().map(String::as_str).collect();
+
+    rewrite_package(buf, &paths, |path, xml| {
+        let priorities = &priorities[path];
+        let xml = rewrite_priorities(path, &xml, "<cfRule ", &priorities.classic)?;
+        rewrite_priorities(path, &xml, "<x14:cfRule ", &priorities.x14)
+    })
+}
+
+// Replaces the priority attribute of every `tag` element through `priorities`.
+fn rewrite_priorities(path: &str, xml: &str, tag: &str, priorities: &HashMap<u32, u32>) -> ZipResult<String> {
+    const ATTRIBUTE: &str = "priority=\"";
+
+    let mut result = String::with_capacity(xml.len());
+    let mut rest = xml;
+
+    while let Some(start) = rest.find(tag) {
+        let Some(tag_length) = rest[start..].find('>') else {
+            return Err(malformed_part(path, &format!("unterminated {}element", tag.trim_start_matches('<'))));
+        };
+        let tag_end = start + tag_length;
+        let Some(attribute) = rest[start..tag_end].find(ATTRIBUTE) else {
+            result.push_str(&rest[..tag_end]);
+            rest = &rest[tag_end..];
+            continue;
+        };
+
+        let value_start = start + attribute + ATTRIBUTE.len();
+        let Some(value_length) = rest[value_start..tag_end].find('"') else {
+            return Err(malformed_part(path, "unterminated priority attribute"));
+        };
+        let value_end = value_start + value_length;
+        result.push_str(&rest[..value_start]);
+
+        match rest[value_start..value_end].parse::<u32>().ok().and_then(|p| priorities.get(&p)) {
+            Some(priority) => result.push_str(&priority.to_string()),
+            None => result.push_str(&rest[value_start..value_end]),
+        }
+
+        rest = &rest[value_end..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn malformed_part(path: &str, problem: &str) -> ZipError {
+    ZipError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("Unexpected XML in {path}: {problem}")))
+}
+
+// Copy the saved package entry by entry, passing the XML of the given parts
+// through `rewrite` and recompressing them.
+fn rewrite_package(
+    buf: Vec<u8>,
+    parts: &[&str],
+    mut rewrite: impl FnMut(&str, String) -> ZipResult<String>,
+) -> ZipResult<Vec<u8>> {
+    let mut archive = ZipArchive::new(Cursor::new(buf))?;
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+
+    for index in 0..archive.len() {
+        let name = archive.by_index_raw(index)?.name().to_string();
+        if !parts.contains(&name.as_str()) {
+            writer.raw_copy_file(archive.by_index_raw(index)?)?;
+            continue;
+        }
+
+        let mut xml = String::new();
+        archive.by_index(index)?.read_to_string(&mut xml)?;
+        let xml = rewrite(&name, xml)?;
+
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        writer.start_file(name, options)?;
+        writer.write_all(xml.as_bytes())?;
+    }
+
+    Ok(writer.finish()?.into_inner())
+}
+
+// Legacy Excel password hash used by the workbookPassword attribute, see
+// ECMA-376 Part 4, 18.2.29. rust_xlsxwriter keeps its copy crate private.
+fn hash_password(password: &str) -> u16 {
+    if password.is_empty() {
+        return 0;
+    }
+
+    let mut hash: u16 = 0;
+    for byte in password.as_bytes().iter().rev() {
+        hash = ((hash >> 14) & 0x01) | ((hash << 1) & 0x7FFF);
+        hash ^= u16::from(*byte);
+    }
+
+    hash = ((hash >> 14) & 0x01) | ((hash << 1) & 0x7FFF);
+    hash ^= password.len() as u16;
+    hash ^ 0xCE4B
+}
+
+fn insert_note(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    text: String,
+    options: NoteOptions,
+) -> Result<&mut Worksheet, XlsxError> {
+    let mut note = Note::new(&text);
+
+    if let Some(author) = options.author {
+        note = note.set_author(&author);
+    }
+
+    if let Some(visible) = options.visible {
+        note = note.set_visible(visible);
+    }
+
+    if let Some(width) = options.width {
+        note = note.set_width(width);
+    }
+
+    if let Some(height) = options.height {
+        note = note.set_height(height);
+    }
+
+    worksheet.insert_note(row, col, &note)
+}
+
+fn insert_chart(
+    worksheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    kind: ChartKind,
+    series: Vec<Vec<ChartSeriesOption>>,
+    options: Vec<ChartOption>,
+) -> Result<&mut Worksheet, XlsxError> {
+    let chart = build_chart(&kind, &series, &options)?;
+
+    let (x_offset, y_offset) = options
+        .iter()
+        .find_map(|opt| match opt {
+            ChartOption::Offset(x, y) => Some((*x, *y)),
+            _ => None,
+        })
+        .unwrap_or((0, 0));
+
+    worksheet.insert_chart_with_offset(row, col, &chart, x_offset, y_offset)
+}
+
+fn build_chart(
+    kind: &ChartKind,
+    series: &[Vec<ChartSeriesOption>],
+    options: &[ChartOption],
+) -> Result<Chart, XlsxError> {
+    let mut chart = Chart::new(convert_chart_kind(kind));
+
+    for series_options in series {
+        check_error_bars(kind, series_options)?;
+        apply_series_options(chart.add_series(), series_options);
+    }
+
+    for opt in options {
+        match opt {
+            ChartOption::Title(title) => {
+                chart.title().set_name(title);
+            }
+            ChartOption::XAxis(axis_options) => apply_axis_options(chart.x_axis(), axis_options),
+            ChartOption::YAxis(axis_options) => apply_axis_options(chart.y_axis(), axis_options),
+            ChartOption::X2Axis(axis_options) => apply_axis_options(chart.x2_axis(), axis_options),
+            ChartOption::Y2Axis(axis_options) => apply_axis_options(chart.y2_axis(), axis_options),
+            ChartOption::Legend(position) => match position {
+                LegendPosition::Right => {
+                    chart.legend().set_position(ChartLegendPosition::Right);
+                }
+                LegendPosition::Left => {
+                    chart.legend().set_position(ChartLegendPosition::Left);
+                }
+                LegendPosition::Top => {
+                    chart.legend().set_position(ChartLegendPosition::Top);
+                }
+                LegendPosition::Bottom => {
+                    chart.legend().set_position(ChartLegendPosition::Bottom);
+                }
+                LegendPosition::TopRight => {
+                    chart.legend().set_position(ChartLegendPosition::TopRight);
+                }
+                LegendPosition::None => {
+                    chart.legend().set_hidden();
+                }
+            },
+            ChartOption::Size(width, height) => {
+                chart.set_width(*width).set_height(*height);
+            }
+            ChartOption::Combine(secondary_kind, secondary_series) => {
+                let mut secondary = build_chart(secondary_kind, secondary_series, &[])?;
+                // Only the primary chart is validated on insertion
+                secondary.validate()?;
+                chart.combine(&secondary);
+            }
+            // The offset belongs to the anchor, not the chart; see insert_chart
+            ChartOption::Offset(_, _) => {}
+        }
+    }
+
+    Ok(chart)
+}
+
+// Excel only draws horizontal error bars on bar and scatter charts, and only
+// horizontal ones on bar charts; rust_xlsxwriter drops the others silently.
+fn check_error_bars(kind: &ChartKind, options: &[ChartSeriesOption]) -> Result<(), XlsxError> {
+    let (horizontal, vertical) = match kind {
+        ChartKind::Bar | ChartKind::BarStacked | ChartKind::BarPercentStacked => (true, false),
+        ChartKind::Scatter
+        | ChartKind::ScatterStraight
+        | ChartKind::ScatterStraightWithMarkers
+        | ChartKind::ScatterSmooth
+        | ChartKind::ScatterSmoothWithMarkers => (true, true),
+        _ => (false, true),
+    };
+
+    for opt in options {
+        match opt {
+            ChartSeriesOption::XErrorBars(_, _) if !horizontal => {
+                return Err(XlsxError::ParameterError(
+                    "X error bars are only supported on bar and scatter charts.".to_string(),
+                ));
+            }
+            ChartSeriesOption::YErrorBars(_, _) if !vertical => {
+                return Err(XlsxError::ParameterError(
+                    "Y error bars aren't supported on bar charts, use X error bars.".to_string(),
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn apply_series_options(series: &mut ChartSeries, options: &[ChartSeriesOption]) {
+    // Fill and line settings are collected first since they share one ChartFormat
+    let mut fill: Option<ChartSolidFill> = None;
+    let mut line: Option<ChartLine> = None;
+
+    for opt in options {
+        match opt {
+            ChartSeriesOption::Name(name) => {
+                series.set_name(name);
+            }
+            ChartSeriesOption::Categories(range) => {
+                series.set_categories(range);
+            }
+            ChartSeriesOption::Values(range) => {
+                series.set_values(range);
+            }
+            ChartSeriesOption::FillColor(color_hex) => {
+                if let Some(color) = parse_hex_color(color_hex) {
+                    fill.get_or_insert_with(ChartSolidFill::new).set_color(color);
+                }
+            }
+            ChartSeriesOption::LineColor(color_hex) => {
+                if let Some(color) = parse_hex_color(color_hex) {
+                    line.get_or_insert_with(ChartLine::new).set_color(color);
+                }
+            }
+            ChartSeriesOption::LineDash(style) => {
+                line.get_or_insert_with(ChartLine::new)
+                    .set_dash_type(convert_line_dash_style(style));
+            }
+            ChartSeriesOption::LineWidth(width) => {
+                line.get_or_insert_with(ChartLine::new).set_width(*width);
+            }
+            ChartSeriesOption::Marker(marker_options) => {
+                series.set_marker(&build_chart_marker(marker_options));
+            }
+            ChartSeriesOption::DataLabels(label_options) => {
+                series.set_data_label(&build_chart_data_label(label_options));
+            }
+            ChartSeriesOption::Trendline(kind, trendline_options) => {
+                series.set_trendline(&build_chart_trendline(kind, trendline_options));
+            }
+            ChartSeriesOption::XErrorBars(kind, error_bars_options) => {
+                series.set_x_error_bars(&build_chart_error_bars(kind, error_bars_options));
+            }
+            ChartSeriesOption::YErrorBars(kind, error_bars_options) => {
+                series.set_y_error_bars(&build_chart_error_bars(kind, error_bars_options));
+            }
+            ChartSeriesOption::SecondaryAxis => {
+                series.set_secondary_axis(true);
+            }
+        }
+    }
+
+    if fill.is_some() || line.is_some() {
+        let mut format = ChartFormat::new();
+        if let Some(fill) = &fill {
+            format.set_solid_fill(fill);
+        }
+        if let Some(line) = &line {
+            format.set_line(line);
+        }
+        series.set_format(&mut format);
+    }
+}
+
+fn apply_axis_options(axis: &mut ChartAxis, options: &[ChartAxisOption]) {
+    for opt in options {
+        match opt {
+            ChartAxisOption::Title(title) => {
+                axis.set_name(title);
+            }
+            ChartAxisOption::Min(min) => {
+                axis.set_min(*min);
+            }
+            ChartAxisOption::Max(max) => {
+                axis.set_max(*max);
+            }
+            ChartAxisOption::MajorUnit(unit) => {
+                axis.set_major_unit(*unit);
+            }
+            ChartAxisOption::MinorUnit(unit) => {
+                axis.set_minor_unit(*unit);
+            }
+            ChartAxisOption::LogBase(base) => {
+                axis.set_log_base(*base);
+            }
+            ChartAxisOption::NumFormat(num_format) => {
+                axis.set_num_format(num_format);
+            }
+            ChartAxisOption::Reverse => {
+                axis.set_reverse();
+            }
+        }
+    }
+}
+
+fn build_chart_marker(options: &[ChartMarkerOption]) -> ChartMarker {
+    let mut marker = ChartMarker::new();
+    let mut format: Option<ChartFormat> = None;
+
+    for opt in options {
+        match opt {
+            ChartMarkerOption::Type(kind) => match kind {
+                MarkerKind::Automatic => {
+                    marker.set_automatic();
+                }
+                MarkerKind::None => {
+                    marker.set_none();
+                }
+                MarkerKind::Square => {
+                    marker.set_type(ChartMarkerType::Square);
+                }
+                MarkerKind::Diamond => {
+                    marker.set_type(ChartMarkerType::Diamond);
+                }
+                MarkerKind::Triangle => {
+                    marker.set_type(ChartMarkerType::Triangle);
+                }
+                MarkerKind::X => {
+                    marker.set_type(ChartMarkerType::X);
+                }
+                MarkerKind::Star => {
+                    marker.set_type(ChartMarkerType::Star);
+                }
+                MarkerKind::ShortDash => {
+                    marker.set_type(ChartMarkerType::ShortDash);
+                }
+                MarkerKind::LongDash => {
+                    marker.set_type(ChartMarkerType::LongDash);
+                }
+                MarkerKind::Circle => {
+                    marker.set_type(ChartMarkerType::Circle);
+                }
+                MarkerKind::PlusSign => {
+                    marker.set_type(ChartMarkerType::PlusSign);
+                }
+            },
+            ChartMarkerOption::Size(size) => {
+                marker.set_size(*size);
+            }
+            ChartMarkerOption::FillColor(color_hex) => {
+                if let Some(color) = parse_hex_color(color_hex) {
+                    format
+                        .get_or_insert_with(ChartFormat::new)
+                        .set_solid_fill(ChartSolidFill::new().set_color(color));
+                }
+            }
+            ChartMarkerOption::LineColor(color_hex) => {
+                if let Some(color) = parse_hex_color(color_hex) {
+                    format
+                        .get_or_insert_with(ChartFormat::new)
+                        .set_line(ChartLine::new().set_color(color));
+                }
+            }
+        }
+    }
+
+    if let Some(format) = format.as_mut() {
+        marker.set_format(format);
+    }
+
+    marker
+}
+
+fn build_chart_data_label(options: &[ChartDataLabelOption]) -> ChartDataLabel {
+    let mut data_label = ChartDataLabel::new();
+
+    for opt in options {
+        match opt {
+            ChartDataLabelOption::Value => {
+                data_label.show_value();
+            }
+            ChartDataLabelOption::Percentage => {
+                data_label.show_percentage();
+            }
+            ChartDataLabelOption::CategoryName => {
+                data_label.show_category_name();
+            }
+            ChartDataLabelOption::SeriesName => {
+                data_label.show_series_name();
+            }
+            ChartDataLabelOption::LeaderLines => {
+                data_label.show_leader_lines();
+            }
+            ChartDataLabelOption::LegendKey => {
+                data_label.show_legend_key();
+            }
+            ChartDataLabelOption::Position(position) => {
+                data_label.set_position(convert_data_label_position(position));
+            }
+            ChartDataLabelOption::NumFormat(num_format) => {
+                data_label.set_num_format(num_format);
+            }
+        }
+    }
+
+    data_label
+}
+
+fn build_chart_trendline(kind: &TrendlineKind, options: &[ChartTrendlineOption]) -> ChartTrendline {
+    let mut trendline = ChartTrendline::new();
+    let mut line: Option<ChartLine> = None;
+
+    trendline.set_type(match kind {
+        TrendlineKind::Linear => ChartTrendlineType::Linear,
+        TrendlineKind::Exponential => ChartTrendlineType::Exponential,
+        TrendlineKind::Logarithmic => ChartTrendlineType::Logarithmic,
+        TrendlineKind::Power => ChartTrendlineType::Power,
+        TrendlineKind::Polynomial(order) => ChartTrendlineType::Polynomial(*order),
+        TrendlineKind::MovingAverage(period) => ChartTrendlineType::MovingAverage(*period),
+    });
+
+    for opt in options {
+        match opt {
+            ChartTrendlineOption::Name(name) => {
+                trendline.set_name(name);
+            }
+            ChartTrendlineOption::LineColor(color_hex) => {
+                if let Some(color) = parse_hex_color(color_hex) {
+                    line.get_or_insert_with(ChartLine::new).set_color(color);
+                }
+            }
+            ChartTrendlineOption::LineDash(style) => {
+                line.get_or_insert_with(ChartLine::new)
+                    .set_dash_type(convert_line_dash_style(style));
+            }
+            ChartTrendlineOption::LineWidth(width) => {
+                line.get_or_insert_with(ChartLine::new).set_width(*width);
+            }
+            ChartTrendlineOption::DisplayEquation => {
+                trendline.display_equation(true);
+            }
+            ChartTrendlineOption::DisplayRSquared => {
+                trendline.display_r_squared(true);
+            }
+            ChartTrendlineOption::ForwardPeriod(period) => {
+                trendline.set_forward_period(*period);
+            }
+            ChartTrendlineOption::BackwardPeriod(period) => {
+                trendline.set_backward_period(*period);
+            }
+        }
+    }
+
+    if let Some(line) = line.as_mut() {
+        trendline.set_format(line);
+    }
+
+    trendline
+}
+
+fn build_chart_error_bars(kind: &ErrorBarsKind, options: &[ChartErrorBarsOption]) -> ChartErrorBars {
+    let mut error_bars = ChartErrorBars::new();
+    let mut line: Option<ChartLine> = None;
+
+    error_bars.set_type(match kind {
+        ErrorBarsKind::FixedValue(value) => ChartErrorBarsType::FixedValue(*value),
+        ErrorBarsKind::Percentage(value) => ChartErrorBarsType::Percentage(*value),
+        ErrorBarsKind::StandardDeviation(value) => ChartErrorBarsType::StandardDeviation(*value),
+        ErrorBarsKind::StandardError => ChartErrorBarsType::StandardError,
+    });
+
+    for opt in options {
+        match opt {
+            ChartErrorBarsOption::Direction(direction) => {
+                error_bars.set_direction(match direction {
+                    ErrorBarsDirection::Both => ChartErrorBarsDirection::Both,
+                    ErrorBarsDirection::Minus => ChartErrorBarsDirection::Minus,
+                    ErrorBarsDirection::Plus => ChartErrorBarsDirection::Plus,
+                });
+            }
+            ChartErrorBarsOption::EndCap(enable) => {
+                error_bars.set_end_cap(*enable);
+            }
+            ChartErrorBarsOption::LineColor(color_hex) => {
+                if let Some(color) = parse_hex_color(color_hex) {
+                    line.get_or_insert_with(ChartLine::new).set_color(color);
+                }
+            }
+            ChartErrorBarsOption::LineDash(style) => {
+                line.get_or_insert_with(ChartLine::new)
+                    .set_dash_type(convert_line_dash_style(style));
+            }
+        }
+    }
+
+    if let Some(line) = line.as_mut() {
+        error_bars.set_format(line);
+    }
+
+    error_bars
+}
+
+fn build_sparkline(range: &str, options: &[SparklineOption]) -> Sparkline {
+    let mut sparkline = Sparkline::new().set_range(range);
+
+    for opt in options {
+        sparkline = match opt {
+            SparklineOption::Type(kind) => sparkline.set_type(match kind {
+                SparklineKind::Line => SparklineType::Line,
+                SparklineKind::Column => SparklineType::Column,
+                SparklineKind::WinLose => SparklineType::WinLose,
+            }),
+            SparklineOption::HighPoint => sparkline.show_high_point(true),
+            SparklineOption::LowPoint => sparkline.show_low_point(true),
+            SparklineOption::FirstPoint => sparkline.show_first_point(true),
+            SparklineOption::LastPoint => sparkline.show_last_point(true),
+            SparklineOption::NegativePoints => sparkline.show_negative_points(true),
+            SparklineOption::Markers => sparkline.show_markers(true),
+            SparklineOption::Axis => sparkline.show_axis(true),
+            SparklineOption::Color(color_hex) => match parse_hex_color(color_hex) {
+                Some(color) => sparkline.set_sparkline_color(color),
+                None => sparkline,
+            },
+            SparklineOption::HighPointColor(color_hex) => match parse_hex_color(color_hex) {
+                Some(color) => sparkline.set_high_point_color(color),
+                None => sparkline,
+            },
+            SparklineOption::LowPointColor(color_hex) => match parse_hex_color(color_hex) {
+                Some(color) => sparkline.set_low_point_color(color),
+                None => sparkline,
+            },
+            SparklineOption::FirstPointColor(color_hex) => match parse_hex_color(color_hex) {
+                Some(color) => sparkline.set_first_point_color(color),
+                None => sparkline,
+            },
+            SparklineOption::LastPointColor(color_hex) => match parse_hex_color(color_hex) {
+                Some(color) => sparkline.set_last_point_color(color),
+                None => sparkline,
+            },
+            SparklineOption::NegativePointsColor(color_hex) => match parse_hex_color(color_hex) {
+                Some(color) => sparkline.set_negative_points_color(color),
+                None => sparkline,
+            },
+            SparklineOption::MarkersColor(color_hex) => match parse_hex_color(color_hex) {
+                Some(color) => sparkline.set_markers_color(color),
+                None => sparkline,
+            },
+            SparklineOption::LineWeight(weight) => sparkline.set_line_weight(*weight),
+            SparklineOption::Min(min) => sparkline.set_custom_min(*min),
+            SparklineOption::Max(max) => sparkline.set_custom_max(*max),
+            SparklineOption::GroupMin => sparkline.set_group_min(true),
+            SparklineOption::GroupMax => sparkline.set_group_max(true),
+            SparklineOption::Style(style) => sparkline.set_style(*style),
+        };
+    }
+
+    sparkline
+}
+
+#[allow(clippy::too_many_arguments)]
+fn add_conditional_format<'a>(
+    worksheet: &'a mut Worksheet,
+    format_cache: &mut FormatCache,
+    rules: &mut Vec<ConditionalFormatRule>,
+    first_row: u32,
+    first_col: u16,
+    last_row: u32,
+    last_col: u16,
+    rule: ConditionalRule,
+    options: Vec<ConditionalFormatOption>,
+) -> Result<&'a mut Worksheet, XlsxError> {
+    let (format_options, options): (Vec<_>, Vec<_>) =
+        options.into_iter().partition(|opt| matches!(opt, ConditionalFormatOption::Format(_)));
+
+    let mut cell_formats = Vec::new();
+    for opt in format_options {
+        if let ConditionalFormatOption::Format(formats) = opt {
+            cell_formats.extend(formats);
+        }
+    }
+    let format = format_cache.get(None, cell_formats).clone();
+
+    match rule {
+        ConditionalRule::Cell(cell_rule) => {
+            let conditional_format = ConditionalFormatCell::new()
+                .set_rule(convert_cell_rule(cell_rule))
+                .set_format(format);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::Text(text_rule) => {
+            let conditional_format = ConditionalFormatText::new()
+                .set_rule(match text_rule {
+                    TextRule::Contains(text) => ConditionalFormatTextRule::Contains(text),
+                    TextRule::DoesNotContain(text) => ConditionalFormatTextRule::DoesNotContain(text),
+                    TextRule::BeginsWith(text) => ConditionalFormatTextRule::BeginsWith(text),
+                    TextRule::EndsWith(text) => ConditionalFormatTextRule::EndsWith(text),
+                })
+                .set_format(format);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::Date(date_rule) => {
+            let conditional_format = ConditionalFormatDate::new()
+                .set_rule(match date_rule {
+                    DateRule::Yesterday => ConditionalFormatDateRule::Yesterday,
+                    DateRule::Today => ConditionalFormatDateRule::Today,
+                    DateRule::Tomorrow => ConditionalFormatDateRule::Tomorrow,
+                    DateRule::LastSevenDays => ConditionalFormatDateRule::Last7Days,
+                    DateRule::LastWeek => ConditionalFormatDateRule::LastWeek,
+                    DateRule::ThisWeek => ConditionalFormatDateRule::ThisWeek,
+                    DateRule::NextWeek => ConditionalFormatDateRule::NextWeek,
+                    DateRule::LastMonth => ConditionalFormatDateRule::LastMonth,
+                    DateRule::ThisMonth => ConditionalFormatDateRule::ThisMonth,
+                    DateRule::NextMonth => ConditionalFormatDateRule::NextMonth,
+                })
+                .set_format(format);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::Blanks => {
+            let conditional_format = ConditionalFormatBlank::new().set_format(format);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::NoBlanks => {
+            let conditional_format = ConditionalFormatBlank::new().invert().set_format(format);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::Errors => {
+            let conditional_format = ConditionalFormatError::new().set_format(format);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::NoErrors => {
+            let conditional_format = ConditionalFormatError::new().invert().set_format(format);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::Duplicate => {
+            let conditional_format = ConditionalFormatDuplicate::new().set_format(format);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::Unique => {
+            let conditional_format = ConditionalFormatDuplicate::new().invert().set_format(format);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::Top(rank) => {
+            let conditional_format = ConditionalFormatTop::new()
+                .set_rule(ConditionalFormatTopRule::Top(rank))
+                .set_format(format);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::Bottom(rank) => {
+            let conditional_format = ConditionalFormatTop::new()
+                .set_rule(ConditionalFormatTopRule::Bottom(rank))
+                .set_format(format);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::TopPercent(percent) => {
+            let conditional_format = ConditionalFormatTop::new()
+                .set_rule(ConditionalFormatTopRule::TopPercent(percent))
+                .set_format(format);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::BottomPercent(percent) => {
+            let conditional_format = ConditionalFormatTop::new()
+                .set_rule(ConditionalFormatTopRule::BottomPercent(percent))
+                .set_format(format);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::Formula(formula) => {
+            let conditional_format = ConditionalFormatFormula::new()
+                .set_rule(formula.as_str())
+                .set_format(format);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::TwoColorScale(scale_options) => {
+            let mut conditional_format = ConditionalFormat2ColorScale::new();
+            for opt in scale_options {
+                conditional_format = match opt {
+                    ColorScaleOption::Min(threshold) => {
+                        let (rule_type, value) = convert_threshold(threshold);
+                        conditional_format.set_minimum(rule_type, value)
+                    }
+                    ColorScaleOption::Max(threshold) => {
+                        let (rule_type, value) = convert_threshold(threshold);
+                        conditional_format.set_maximum(rule_type, value)
+                    }
+                    ColorScaleOption::MinColor(color_hex) => match parse_hex_color(&color_hex) {
+                        Some(color) => conditional_format.set_minimum_color(color),
+                        None => conditional_format,
+                    },
+                    ColorScaleOption::MaxColor(color_hex) => match parse_hex_color(&color_hex) {
+                        Some(color) => conditional_format.set_maximum_color(color),
+                        None => conditional_format,
+                    },
+                    ColorScaleOption::Mid(_) | ColorScaleOption::MidColor(_) => {
+                        return Err(XlsxError::ParameterError(
+                            "Two color scales have no midpoint, use a three color scale instead.".to_string(),
+                        ))
+                    }
+                };
+            }
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::ThreeColorScale(scale_options) => {
+            let mut conditional_format = ConditionalFormat3ColorScale::new();
+            for opt in scale_options {
+                conditional_format = match opt {
+                    ColorScaleOption::Min(threshold) => {
+                        let (rule_type, value) = convert_threshold(threshold);
+                        conditional_format.set_minimum(rule_type, value)
+                    }
+                    ColorScaleOption::Mid(threshold) => {
+                        let (rule_type, value) = convert_threshold(threshold);
+                        conditional_format.set_midpoint(rule_type, value)
+                    }
+                    ColorScaleOption::Max(threshold) => {
+                        let (rule_type, value) = convert_threshold(threshold);
+                        conditional_format.set_maximum(rule_type, value)
+                    }
+                    ColorScaleOption::MinColor(color_hex) => match parse_hex_color(&color_hex) {
+                        Some(color) => conditional_format.set_minimum_color(color),
+                        None => conditional_format,
+                    },
+                    ColorScaleOption::MidColor(color_hex) => match parse_hex_color(&color_hex) {
+                        Some(color) => conditional_format.set_midpoint_color(color),
+                        None => conditional_format,
+                    },
+                    ColorScaleOption::MaxColor(color_hex) => match parse_hex_color(&color_hex) {
+                        Some(color) => conditional_format.set_maximum_color(color),
+                        None => conditional_format,
+                    },
+                };
+            }
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::DataBar(bar_options) => {
+            let conditional_format = build_data_bar(bar_options);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+        ConditionalRule::IconSet(style, icon_options) => {
+            let conditional_format = build_icon_set(&style, icon_options);
+            add_conditional_rule(worksheet, rules, first_row, first_col, last_row, last_col, conditional_format, &options)
+        }
+    }
+}
+
+// The multi-range and stop-if-true setters are generated per conditional
+// format type in rust_xlsxwriter, so expose them through a common trait.
+trait ConditionalFormatCommon: ConditionalFormat + Send + Sync + Sized {
+    fn with_multi_range(self, range: String) -> Self;
+    fn with_stop_if_true(self, enable: bool) -> Self;
+}
+
+macro_rules! impl_conditional_format_common {
+    ($($t:ty),*) => {
+        $(
+            impl ConditionalFormatCommon for $t {
+                fn with_multi_range(self, range: String) -> Self {
+                    self.set_multi_range(range)
+                }
+
+                fn with_stop_if_true(self, enable: bool) -> Self {
+                    self.set_stop_if_true(enable)
+                }
+            }
+        )*
+    };
+}
+
+impl_conditional_format_common!(
+    ConditionalFormatCell,
+    ConditionalFormatText,
+    ConditionalFormatDate,
+    ConditionalFormatBlank,
+    ConditionalFormatError,
+    ConditionalFormatDuplicate,
+    ConditionalFormatTop,
+    ConditionalFormatFormula,
+    ConditionalFormat2ColorScale,
+    ConditionalFormat3ColorScale,
+    ConditionalFormatDataBar,
+    ConditionalFormatIconSet
+);
+
+#[allow(clippy::too_many_arguments)]
+fn add_conditional_rule<'a, T: ConditionalFormatCommon>(
+    worksheet: &'a mut Worksheet,
+    rules: &mut Vec<ConditionalFormatRule>,
+    first_row: u32,
+    first_col: u16,
+    last_row: u32,
+    last_col: u16,
+    conditional_format: T,
+    options: &[ConditionalFormatOption],
+) -> Result<&'a mut Worksheet, XlsxError> {
+    let mut conditional_format = conditional_format;
+    for opt in options {
+        conditional_format = match opt {
+            ConditionalFormatOption::Ranges(ranges) => {
+                let mut multi_range = cell_range(first_row, first_col, last_row, last_col);
+                for range in ranges {
+                    multi_range.push(' ');
+                    multi_range.push_str(range);
+                }
+                conditional_format.with_multi_range(multi_range)
+            }
+            ConditionalFormatOption::StopIfTrue => conditional_format.with_stop_if_true(true),
+            ConditionalFormatOption::Format(_) | ConditionalFormatOption::Priority(_) => conditional_format,
+        };
+    }
+
+    let worksheet = worksheet.add_conditional_format(first_row, first_col, last_row, last_col, &conditional_format)?;
+
+    // Keyed like rust_xlsxwriter stores it, see conditional_format_priorities().
+    let range = match conditional_format.multi_range() {
+        multi_range if multi_range.is_empty() => cell_range(first_row, first_col, last_row, last_col),
+        multi_range => multi_range,
+    };
+    rules.push(ConditionalFormatRule {
+        range,
+        priority: conditional_format_priority(options),
+        x14: conditional_format.has_x14_extensions(),
+    });
+
+    Ok(worksheet)
+}
+
+fn conditional_format_priority(options: &[ConditionalFormatOption]) -> Option<u32> {
+    options.iter().find_map(|opt| match opt {
+        ConditionalFormatOption::Priority(priority) => Some(*priority),
+        _ => None,
+    })
+}
+
+fn build_data_bar(options: Vec<DataBarOption>) -> ConditionalFormatDataBar {
+    let mut data_bar = ConditionalFormatDataBar::new();
+
+    for opt in options {
+        data_bar = match opt {
+            DataBarOption::Min(threshold) => {
+                let (rule_type, value) = convert_threshold(threshold);
+                data_bar.set_minimum(rule_type, value)
+            }
+            DataBarOption::Max(threshold) => {
+                let (rule_type, value) = convert_threshold(threshold);
+                data_bar.set_maximum(rule_type, value)
+            }
+            DataBarOption::FillColor(color_hex) => match parse_hex_color(&color_hex) {
+                Some(color) => data_bar.set_fill_color(color),
+                None => data_bar,
+            },
+            DataBarOption::BorderColor(color_hex) => match parse_hex_color(&color_hex) {
+                Some(color) => data_bar.set_border_color(color),
+                None => data_bar,
+            },
+            DataBarOption::NegativeFillColor(color_hex) => match parse_hex_color(&color_hex) {
+                Some(color) => data_bar.set_negative_fill_color(color),
+                None => data_bar,
+            },
+            DataBarOption::NegativeBorderColor(color_hex) => match parse_hex_color(&color_hex) {
+                Some(color) => data_bar.set_negative_border_color(color),
+                None => data_bar,
+            },
+            DataBarOption::Solid => data_bar.set_solid_fill(true),
+            DataBarOption::NoBorder => data_bar.set_border_off(true),
+            DataBarOption::Direction(direction) => data_bar.set_direction(match direction {
+                DataBarDirection::Context => ConditionalFormatDataBarDirection::Context,
+                DataBarDirection::LeftToRight => ConditionalFormatDataBarDirection::LeftToRight,
+                DataBarDirection::RightToLeft => ConditionalFormatDataBarDirection::RightToLeft,
+            }),
+            DataBarOption::AxisPosition(position) => data_bar.set_axis_position(match position {
+                DataBarAxisPosition::Automatic => ConditionalFormatDataBarAxisPosition::Automatic,
+                DataBarAxisPosition::Midpoint => ConditionalFormatDataBarAxisPosition::Midpoint,
+                DataBarAxisPosition::None => ConditionalFormatDataBarAxisPosition::None,
+            }),
+            DataBarOption::AxisColor(color_hex) => match parse_hex_color(&color_hex) {
+                Some(color) => data_bar.set_axis_color(color),
+                None => data_bar,
+            },
+            DataBarOption::BarOnly => data_bar.set_bar_only(true),
+        };
+    }
+
+    data_bar
+}
+
+fn build_icon_set(style: &IconStyle, options: Vec<IconSetOption>) -> ConditionalFormatIconSet {
+    let mut icon_set = ConditionalFormatIconSet::new().set_icon_type(match style {
+        IconStyle::ThreeArrows => ConditionalFormatIconType::ThreeArrows,
+        IconStyle::ThreeArrowsGray => ConditionalFormatIconType::ThreeArrowsGray,
+        IconStyle::ThreeFlags => ConditionalFormatIconType::ThreeFlags,
+        IconStyle::ThreeTrafficLights => ConditionalFormatIconType::ThreeTrafficLights,
+        IconStyle::ThreeTrafficLightsWithRim => ConditionalFormatIconType::ThreeTrafficLightsWithRim,
+        IconStyle::ThreeSigns => ConditionalFormatIconType::ThreeSigns,
+        IconStyle::ThreeSymbolsCircled => ConditionalFormatIconType::ThreeSymbolsCircled,
+        IconStyle::ThreeSymbols => ConditionalFormatIconType::ThreeSymbols,
+        IconStyle::ThreeStars => ConditionalFormatIconType::ThreeStars,
+        IconStyle::ThreeTriangles => ConditionalFormatIconType::ThreeTriangles,
+        IconStyle::FourArrows => ConditionalFormatIconType::FourArrows,
+        IconStyle::FourArrowsGray => ConditionalFormatIconType::FourArrowsGray,
+        IconStyle::FourRedToBlack => ConditionalFormatIconType::FourRedToBlack,
+        IconStyle::FourRatings => ConditionalFormatIconType::FourHistograms,
+        IconStyle::FourTrafficLights => ConditionalFormatIconType::FourTrafficLights,
+        IconStyle::FiveArrows => ConditionalFormatIconType::FiveArrows,
+        IconStyle::FiveArrowsGray => ConditionalFormatIconType::FiveArrowsGray,
+        IconStyle::FiveRatings => ConditionalFormatIconType::FiveHistograms,
+        IconStyle::FiveQuadrants => ConditionalFormatIconType::FiveQuadrants,
+        IconStyle::FiveBoxes => ConditionalFormatIconType::FiveBoxes,
+    });
+
+    for opt in options {
+        icon_set = match opt {
+            IconSetOption::Reverse => icon_set.reverse_icons(true),
+            IconSetOption::IconsOnly => icon_set.show_icons_only(true),
+            IconSetOption::Thresholds(thresholds) => {
+                let icons: Vec<ConditionalFormatCustomIcon> = thresholds
+                    .into_iter()
+                    .map(|threshold| {
+                        let (rule_type, value) = convert_threshold(threshold);
+                        ConditionalFormatCustomIcon::new().set_rule(rule_type, value)
+                    })
+                    .collect();
+                icon_set.set_icons(&icons)
+            }
+        };
+    }
+
+    icon_set
+}
+
+fn convert_threshold(threshold: ConditionalThreshold) -> (ConditionalFormatType, ConditionalFormatValue) {
+    match threshold {
+        ConditionalThreshold::Automatic => (ConditionalFormatType::Automatic, 0.into()),
+        ConditionalThreshold::Lowest => (ConditionalFormatType::Lowest, 0.into()),
+        ConditionalThreshold::Highest => (ConditionalFormatType::Highest, 0.into()),
+        ConditionalThreshold::Number(value) => (ConditionalFormatType::Number, value.into()),
+        ConditionalThreshold::Percent(value) => (ConditionalFormatType::Percent, value.into()),
+        ConditionalThreshold::Percentile(value) => (ConditionalFormatType::Percentile, value.into()),
+        ConditionalThreshold::Formula(formula) => (ConditionalFormatType::Formula, Formula::new(formula).into()),
+    }
+}
+
+fn convert_table_style(style: TableStyleName) -> Result<TableStyle, XlsxError> {
+    Ok(match style {
+        TableStyleName::None => TableStyle::None,
+        TableStyleName::Light(1) => TableStyle::Light1,
+        TableStyleName::Light(2) => TableStyle::Light2,
+        TableStyleName::Light(3) => TableStyle::Light3,
+        TableStyleName::Light(4) => TableStyle::Light4,
+        TableStyleName::Light(5) => TableStyle::Light5,
+        TableStyleName::Light(6) => TableStyle::Light6,
+        TableStyleName::Light(7) => TableStyle::Light7,
+        TableStyleName::Light(8) => TableStyle::Light8,
+        TableStyleName::Light(9) => TableStyle::Light9,
+        TableStyleName::Light(10) => TableStyle::Light10,
+        TableStyleName::Light(11) => TableStyle::Light11,
+        TableStyleName::Light(12) => TableStyle::Light12,
+        TableStyleName::Light(13) => TableStyle::Light13,
+        TableStyleName::Light(14) => TableStyle::Light14,
+        TableStyleName::Light(15) => TableStyle::Light15,
+        TableStyleName::Light(16) => TableStyle::Light16,
+        TableStyleName::Light(17) => TableStyle::Light17,
+        TableStyleName::Light(18) => TableStyle::Light18,
+        TableStyleName::Light(19) => TableStyle::Light19,
+        TableStyleName::Light(20) => TableStyle::Light20,
+        TableStyleName::Light(21) => TableStyle::Light21,
+        TableStyleName::Medium(1) => TableStyle::Medium1,
+        TableStyleName::Medium(2) => TableStyle::Medium2,
+        TableStyleName::Medium(3) => TableStyle::Medium3,
+        TableStyleName::Medium(4) => TableStyle::Medium4,
+        TableStyleName::Medium(5) => TableStyle::Medium5,
+        TableStyleName::Medium(6) => TableStyle::Medium6,
+        TableStyleName::Medium(7) => TableStyle::Medium7,
+        TableStyleName::Medium(8) => TableStyle::Medium8,
+        TableStyleName::Medium(9) => TableStyle::Medium9,
+        TableStyleName::Medium(10) => TableStyle::Medium10,
+        TableStyleName::Medium(11) => TableStyle::Medium11,
+        TableStyleName::Medium(12) => TableStyle::Medium12,
+        TableStyleName::Medium(13) => TableStyle::Medium13,
+        TableStyleName::Medium(14) => TableStyle::Medium14,
+        TableStyleName::Medium(15) => TableStyle::Medium15,
+        TableStyleName::Medium(16) => TableStyle::Medium16,
+        TableStyleName::Medium(17) => TableStyle::Medium17,
+        TableStyleName::Medium(18) => TableStyle::Medium18,
+        TableStyleName::Medium(19) => TableStyle::Medium19,
+        TableStyleName::Medium(20) => TableStyle::Medium20,
+        TableStyleName::Medium(21) => TableStyle::Medium21,
+        TableStyleName::Medium(22) => TableStyle::Medium22,
+        TableStyleName::Medium(23) => TableStyle::Medium23,
+        TableStyleName::Medium(24) => TableStyle::Medium24,
+        TableStyleName::Medium(25) => TableStyle::Medium25,
+        TableStyleName::Medium(26) => TableStyle::Medium26,
+        TableStyleName::Medium(27) => TableStyle::Medium27,
+        TableStyleName::Medium(28) => TableStyle::Medium28,
+        TableStyleName::Dark(1) => TableStyle::Dark1,
+        TableStyleName::Dark(2) => TableStyle::Dark2,
+        TableStyleName::Dark(3) => TableStyle::Dark3,
+        TableStyleName::Dark(4) => TableStyle::Dark4,
+        TableStyleName::Dark(5) => TableStyle::Dark5,
+        TableStyleName::Dark(6) => TableStyle::Dark6,
+        TableStyleName::Dark(7) => TableStyle::Dark7,
+        TableStyleName::Dark(8) => TableStyle::Dark8,
+        TableStyleName::Dark(9) => TableStyle::Dark9,
+        TableStyleName::Dark(10) => TableStyle::Dark10,
+        TableStyleName::Dark(11) => TableStyle::Dark11,
+        TableStyleName::Light(number) | TableStyleName::Medium(number) | TableStyleName::Dark(number) => {
+            return Err(XlsxError::TableError(format!(
+                "Table style number '{number}' is out of range: Light 1-21, Medium 1-28, Dark 1-11."
+            )))
+        }
+    })
+}
+
+fn convert_cell_rule(rule: CellRule) -> ConditionalFormatCellRule<ConditionalFormatValue> {
+    match rule {
+        CellRule::EqualTo(value) => ConditionalFormatCellRule::EqualTo(value.into()),
+        CellRule::NotEqualTo(value) => ConditionalFormatCellRule::NotEqualTo(value.into()),
+        CellRule::GreaterThan(value) => ConditionalFormatCellRule::GreaterThan(value.into()),
+        CellRule::GreaterThanOrEqualTo(value) => ConditionalFormatCellRule::GreaterThanOrEqualTo(value.into()),
+        CellRule::LessThan(value) => ConditionalFormatCellRule::LessThan(value.into()),
+        CellRule::LessThanOrEqualTo(value) => ConditionalFormatCellRule::LessThanOrEqualTo(value.into()),
+        CellRule::Between(min, max) => ConditionalFormatCellRule::Between(min.into(), max.into()),
+        CellRule::NotBetween(min, max) => ConditionalFormatCellRule::NotBetween(min.into(), max.into()),
+    }
+}
+
+impl From<ConditionalValue> for ConditionalFormatValue {
+    fn from(value: ConditionalValue) -> ConditionalFormatValue {
+        match value {
+            ConditionalValue::Number(number) => number.into(),
+            ConditionalValue::Text(text) if text.starts_with('=') => Formula::new(text).into(),
+            ConditionalValue::Text(text) => text.into(),
+        }
+    }
+}
+
+fn add_data_validation(
+    worksheet: &mut Worksheet,
+    first_row: u32,
+    first_col: u16,
+    last_row: u32,
+    last_col: u16,
+    rule: ValidationRule,
+    options: Vec<DataValidationOption>,
+) -> Result<&mut Worksheet, XlsxError> {
+    let mut data_validation = match rule {
+        ValidationRule::List(ListSource::Items(items)) => DataValidation::new().allow_list_strings(&items)?,
+        ValidationRule::List(ListSource::Range(range)) => DataValidation::new().allow_list_formula(Formula::new(range)),
+        ValidationRule::WholeNumber(rule) => {
+            DataValidation::new().allow_whole_number_formula(convert_validation_rule(rule, whole_number_bound)?)
+        }
+        ValidationRule::Decimal(rule) => {
+            DataValidation::new().allow_decimal_number_formula(convert_validation_rule(rule, number_bound)?)
+        }
+        ValidationRule::Date(rule) => DataValidation::new().allow_date_formula(convert_validation_rule(rule, datetime_bound)?),
+        ValidationRule::Time(rule) => DataValidation::new().allow_time_formula(convert_validation_rule(rule, datetime_bound)?),
+        ValidationRule::TextLength(rule) => {
+            DataValidation::new().allow_text_length_formula(convert_validation_rule(rule, whole_number_bound)?)
+        }
+        ValidationRule::Custom(formula) => DataValidation::new().allow_custom(Formula::new(formula)),
+    };
+
+    for opt in options {
+        data_validation = match opt {
+            DataValidationOption::IgnoreBlank(enable) => data_validation.ignore_blank(enable),
+            DataValidationOption::HideDropdown(enable) => data_validation.show_dropdown(!enable),
+            DataValidationOption::InputTitle(title) => data_validation.set_input_title(title)?,
+            DataValidationOption::InputMessage(message) => data_validation.set_input_message(message)?,
+            DataValidationOption::ErrorTitle(title) => data_validation.set_error_title(title)?,
+            DataValidationOption::ErrorMessage(message) => data_validation.set_error_message(message)?,
+            DataValidationOption::ErrorStyle(style) => data_validation.set_error_style(match style {
+                ValidationErrorStyle::Stop => DataValidationErrorStyle::Stop,
+                ValidationErrorStyle::Warning => DataValidationErrorStyle::Warning,
+                ValidationErrorStyle::Information => DataValidationErrorStyle::Information,
+            }),
+        };
+    }
+
+    worksheet.add_data_validation(first_row, first_col, last_row, last_col, &data_validation)
+}
+
+fn convert_validation_rule(
+    rule: CellRule,
+    convert: fn(ConditionalValue) -> Result<Formula, XlsxError>,
+) -> Result<DataValidationRule<Formula>, XlsxError> {
+    Ok(match rule {
+        CellRule::EqualTo(value) => DataValidationRule::EqualTo(convert(value)?),
+        CellRule::NotEqualTo(value) => DataValidationRule::NotEqualTo(convert(value)?),
+        CellRule::GreaterThan(value) => DataValidationRule::GreaterThan(convert(value)?),
+        CellRule::GreaterThanOrEqualTo(value) => DataValidationRule::GreaterThanOrEqualTo(convert(value)?),
+        CellRule::LessThan(value) => DataValidationRule::LessThan(convert(value)?),
+        CellRule::LessThanOrEqualTo(value) => DataValidationRule::LessThanOrEqualTo(convert(value)?),
+        CellRule::Between(min, max) => DataValidationRule::Between(convert(min)?, convert(max)?),
+        CellRule::NotBetween(min, max) => DataValidationRule::NotBetween(convert(min)?, convert(max)?),
+    })
+}
+
+// Validation bounds are written as formulas so that literal values and cell
+// references can be mixed in the same rule.
+fn number_bound(value: ConditionalValue) -> Result<Formula, XlsxError> {
+    match value {
+        ConditionalValue::Number(number) => Ok(Formula::new(number.to_string())),
+        ConditionalValue::Text(text) if text.starts_with('=') => Ok(Formula::new(text)),
+        ConditionalValue::Text(text) => Err(XlsxError::DataValidationError(format!(
+            "Validation bound '{text}' must be a number or a reference starting with '='."
+        ))),
+    }
+}
+
+fn whole_number_bound(value: ConditionalValue) -> Result<Formula, XlsxError> {
+    match value {
+        ConditionalValue::Number(number) if number.fract() != 0.0 => Err(XlsxError::DataValidationError(format!(
+            "Validation bound '{number}' must be a whole number."
+        ))),
+        value => number_bound(value),
+    }
+}
+
+fn datetime_bound(value: ConditionalValue) -> Result<Formula, XlsxError> {
+    match value {
+        ConditionalValue::Text(text) if !text.starts_with('=') => {
+            let datetime = ExcelDateTime::parse_from_str(&text)?;
+            Ok(Formula::new(datetime.to_excel().to_string()))
+        }
+        value => number_bound(value),
+    }
+}
+
+fn protect_sheet(worksheet: &mut Worksheet, options: Vec<ProtectionOption>) -> &mut Worksheet {
+    let mut protection = ProtectionOptions::new();
+    let mut password = None;
+
+    for opt in options {
+        match opt {
+            ProtectionOption::Password(value) => password = Some(value),
+            ProtectionOption::SelectLockedCells(allow) => protection.select_locked_cells = allow,
+            ProtectionOption::SelectUnlockedCells(allow) => protection.select_unlocked_cells = allow,
+            ProtectionOption::FormatCells(allow) => protection.format_cells = allow,
+            ProtectionOption::FormatColumns(allow) => protection.format_columns = allow,
+            ProtectionOption::FormatRows(allow) => protection.format_rows = allow,
+            ProtectionOption::InsertColumns(allow) => protection.insert_columns = allow,
+            ProtectionOption::InsertRows(allow) => protection.insert_rows = allow,
+            ProtectionOption::InsertLinks(allow) => protection.insert_links = allow,
+            ProtectionOption::DeleteColumns(allow) => protection.delete_columns = allow,
+            ProtectionOption::DeleteRows(allow) => protection.delete_rows = allow,
+            ProtectionOption::Sort(allow) => protection.sort = allow,
+            ProtectionOption::Autofilter(allow) => protection.use_autofilter = allow,
+            ProtectionOption::PivotTables(allow) => protection.use_pivot_tables = allow,
+            ProtectionOption::EditScenarios(allow) => protection.edit_scenarios = allow,
+            ProtectionOption::EditObjects(allow) => protection.edit_objects = allow,
+        }
+    }
+
+    if let Some(password) = password {
+        worksheet.protect_with_password(&password);
+    }
+
+    worksheet.protect_with_options(&protection)
+}
+
+fn set_page_setup(worksheet: &mut Worksheet, options: Vec<PageSetupOption>) -> Result<&mut Worksheet, XlsxError> {
+    for opt in options {
+        match opt {
+            PageSetupOption::Orientation(PageOrientation::Portrait) => worksheet.set_portrait(),
+            PageSetupOption::Orientation(PageOrientation::Landscape) => worksheet.set_landscape(),
+            PageSetupOption::PaperSize(paper_size) => worksheet.set_paper_size(paper_size),
+            PageSetupOption::Margins(margins) => {
+                // Negative values leave the Excel default in place
+                let mut values = [-1.0; 6];
+                for margin in margins {
+                    match margin {
+                        MarginOption::Left(value) => values[0] = value,
+                        MarginOption::Right(value) => values[1] = value,
+                        MarginOption::Top(value) => values[2] = value,
+                        MarginOption::Bottom(value) => values[3] = value,
+                        MarginOption::Header(value) => values[4] = value,
+                        MarginOption::Footer(value) => values[5] = value,
+                    }
+                }
+                let [left, right, top, bottom, header, footer] = values;
+                worksheet.set_margins(left, right, top, bottom, header, footer)
+            }
+            PageSetupOption::CenterHorizontally(enable) => worksheet.set_print_center_horizontally(enable),
+            PageSetupOption::CenterVertically(enable) => worksheet.set_print_center_vertically(enable),
+            PageSetupOption::Scale(scale) => {
+                if !(10..=400).contains(&scale) {
+                    return Err(XlsxError::ParameterError(format!(
+                        "Print scale '{scale}' outside Excel range: 10 <= scale <= 400."
+                    )));
+                }
+                worksheet.set_print_scale(scale)
+            }
+            PageSetupOption::FitToPages(width, height) => worksheet.set_print_fit_to_pages(width, height),
+            PageSetupOption::PageOrder(PageOrder::DownThenOver) => worksheet.set_page_order(true),
+            PageSetupOption::PageOrder(PageOrder::OverThenDown) => worksheet.set_page_order(false),
+            PageSetupOption::FirstPageNumber(page_number) => worksheet.set_print_first_page_number(page_number),
+            PageSetupOption::PrintGridlines(enable) => worksheet.set_print_gridlines(enable),
+            PageSetupOption::PrintHeadings(enable) => worksheet.set_print_headings(enable),
+            PageSetupOption::BlackAndWhite(enable) => worksheet.set_print_black_and_white(enable),
+            PageSetupOption::Draft(enable) => worksheet.set_print_draft(enable),
+        };
+    }
+
+    Ok(worksheet)
 }
 
-#[rustler::nif(schedule = "DirtyCpu")]
-fn write_with_properties(sheets: Vec<(String, Vec<Sheet>)>, properties: WorkbookProperties) -> Result<Vec<u8>, String> {
-    write_impl(sheets, Some(properties))
+fn outline_group(first: u32, last: u32, options: Vec<OutlineOption>) -> Result<OutlineGroup, XlsxError> {
+    if first > last {
+        return Err(XlsxError::RowColumnOrderError);
+    }
+
+    let mut group = OutlineGroup { first, last, level: 1, collapsed: false };
+    for opt in options {
+        match opt {
+            OutlineOption::Level(level) => {
+                if !(1..=7).contains(&level) {
+                    return Err(XlsxError::MaxGroupLevelExceeded);
+                }
+                group.level = level;
+            }
+            OutlineOption::Collapsed(collapsed) => group.collapsed = collapsed,
+        }
+    }
+
+    Ok(group)
 }
 
-fn write_impl(sheets: Vec<(String, Vec<Sheet>)>, properties: Option<WorkbookProperties>) -> Result<Vec<u8>, String> {
-    let mut workbook = Workbook::new();
+// rust_xlsxwriter raises the outline level by one each time a row or column
+// is grouped. To give every row the highest level of the groups holding it,
+// group the runs of rows at each level or above once per level, splitting out
+// the collapsed groups of that level so they get their collapsed marker.
+fn outline_ranges(groups: &[OutlineGroup]) -> Vec<(u32, u32, bool)> {
+    let mut ranges = Vec::new();
 
-    if let Some(props) = properties {
-        let mut doc_props = DocProperties::new();
-        if let Some(author) = props.author {
-            doc_props = doc_props.set_author(&author);
-        }
-        if let Some(title) = props.title {
-            doc_props = doc_props.set_title(&title);
-        }
-        if let Some(subject) = props.subject {
-            doc_props = doc_props.set_subject(&subject);
-        }
-        if let Some(manager) = props.manager {
-            doc_props = doc_props.set_manager(&manager);
-        }
-        if let Some(company) = props.company {
-            doc_props = doc_props.set_company(&company);
-        }
-        if let Some(category) = props.category {
-            doc_props = doc_props.set_category(&category);
-        }
-        if let Some(keywords) = props.keywords {
-            doc_props = doc_props.set_keywords(&keywords);
-        }
-        if let Some(comment) = props.comment {
-            doc_props = doc_props.set_comment(&comment);
+    for level in 1..=7 {
+        let runs = merge_ranges(groups.iter().filter(|g| g.level >= level).map(|g| (g.first, g.last)));
+        let collapsed = merge_ranges(
+            groups
+                .iter()
+                .filter(|g| g.level == level && g.collapsed)
+                .map(|g| (g.first, g.last)),
+        );
+
+        for (run_first, run_last) in runs {
+            let mut next = run_first;
+            for &(first, last) in collapsed.iter().filter(|&&(first, _)| first >= run_first && first <= run_last) {
+                if first > next {
+                    ranges.push((next, first - 1, false));
+                }
+                ranges.push((first, last, true));
+                next = last + 1;
+            }
+            if next <= run_last {
+                ranges.push((next, run_last, false));
+            }
         }
-        if let Some(status) = props.status {
-            doc_props = doc_props.set_status(&status);
+    }
+
+    ranges
+}
+
+fn group_rows<'a>(worksheet: &'a mut Worksheet, groups: &[OutlineGroup]) -> Result<&'a mut Worksheet, XlsxError> {
+    for (first, last, collapsed) in outline_ranges(groups) {
+        if collapsed {
+            worksheet.group_rows_collapsed(first, last)?;
+        } else {
+            worksheet.group_rows(first, last)?;
         }
-        workbook.set_properties(&doc_props);
     }
 
-    for (sheet_name, sheet) in sheets {
-        let mut worksheet = workbook.add_worksheet();
+    Ok(worksheet)
+}
 
-        match worksheet.set_name(sheet_name) {
-            Err(e) => return Err(e.to_string()),
-            Ok(_) => (),
+fn group_columns<'a>(worksheet: &'a mut Worksheet, groups: &[OutlineGroup]) -> Result<&'a mut Worksheet, XlsxError> {
+    for (first, last, collapsed) in outline_ranges(groups) {
+        // Column groups were built from u16 columns
+        let (first, last) = (first as u16, last as u16);
+        if collapsed {
+            worksheet.group_columns_collapsed(first, last)?;
+        } else {
+            worksheet.group_columns(first, last)?;
         }
+    }
 
-        for instruction in sheet {
-            worksheet = match instruction {
-                Sheet::SetColumnWidth(col, val) => match worksheet.set_column_width(col, val) {
-                    Ok(ws) => ws,
-                    Err(e) => return Err(e.to_string()),
-                },
-                Sheet::SetRowHeight(row, val) => match worksheet.set_row_height(row, val) {
-                    Ok(ws) => ws,
-                    Err(e) => return Err(e.to_string()),
-                },
-                Sheet::SetColumnRangeWidth(first_col, last_col, width) => {
-                    let mut ws = worksheet;
-                    for col in first_col..=last_col {
-                        ws = match ws.set_column_width(col, width) {
-                            Ok(w) => w,
-                            Err(e) => return Err(e.to_string()),
-                        };
-                    }
-                    ws
-                }
-                Sheet::SetRowRangeHeight(first_row, last_row, height) => {
-                    let mut ws = worksheet;
-                    for row in first_row..=last_row {
-                        ws = match ws.set_row_height(row, height) {
-                            Ok(w) => w,
-                            Err(e) => return Err(e.to_string()),
-                        };
-                    }
-                    ws
-                }
-                Sheet::SetFreezePanes(row, col) => match worksheet.set_freeze_panes(row, col) {
-                    Ok(ws) => ws,
-                    Err(e) => return Err(e.to_string()),
-                },
-                Sheet::SetRowHidden(row) => match worksheet.set_row_hidden(row) {
-                    Ok(ws) => ws,
-                    Err(e) => return Err(e.to_string()),
-                },
-                Sheet::SetColumnHidden(col) => match worksheet.set_column_hidden(col) {
-                    Ok(ws) => ws,
-                    Err(e) => return Err(e.to_string()),
-                },
-                Sheet::SetAutofilter(first_row, first_col, last_row, last_col) => {
-                    match worksheet.autofilter(first_row, first_col, last_row, last_col) {
-                        Ok(ws) => ws,
-                        Err(e) => return Err(e.to_string()),
-                    }
-                }
-                Sheet::MergeRange(first_row, first_col, last_row, last_col, data) => {
-                    match merge_range(worksheet, first_row, first_col, last_row, last_col, data) {
-                        Ok(ws) => ws,
-                        Err(e) => return Err(e.to_string()),
-                    }
-                }
-                Sheet::InsertNote(row, col, text, options) => {
-                    match insert_note(worksheet, row, col, text, options) {
-                        Ok(ws) => ws,
-                        Err(e) => return Err(e.to_string()),
-                    }
-                }
-                Sheet::SetTabColor(color_hex) => {
-                    if let Some(color) = parse_hex_color(&color_hex) {
-                        worksheet.set_tab_color(color)
-                    } else {
-                        worksheet
-                    }
-                }
-                Sheet::SetAutofit => worksheet.autofit(),
-                Sheet::Write(row, col, data) => match write_data(worksheet, row, col, data) {
-                    Ok(ws) => ws,
-                    Err(e) => return Err(e.to_string()),
-                },
-            };
+    Ok(worksheet)
+}
+
+// Sort ranges and merge the overlapping or adjacent ones.
+fn merge_ranges(ranges: impl Iterator<Item = (u32, u32)>) -> Vec<(u32, u32)> {
+    let mut ranges: Vec<(u32, u32)> = ranges.collect();
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(u32, u32)> = Vec::new();
+    for (first, last) in ranges {
+        match merged.last_mut() {
+            Some(previous) if first <= previous.1.saturating_add(1) => previous.1 = previous.1.max(last),
+            _ => merged.push((first, last)),
         }
     }
 
-    return match workbook.save_to_buffer() {
-        Ok(buf) => Ok(buf),
-        Err(e) => Err(e.to_string()),
-    };
+    merged
+}
+
+fn set_outline_summary(worksheet: &mut Worksheet, options: Vec<OutlineSummaryOption>) -> &mut Worksheet {
+    for opt in options {
+        match opt {
+            OutlineSummaryOption::Rows(SummaryRowPosition::Below) => worksheet.group_symbols_above(false),
+            OutlineSummaryOption::Rows(SummaryRowPosition::Above) => worksheet.group_symbols_above(true),
+            OutlineSummaryOption::Columns(SummaryColumnPosition::Right) => worksheet.group_symbols_to_left(false),
+            OutlineSummaryOption::Columns(SummaryColumnPosition::Left) => worksheet.group_symbols_to_left(true),
+        };
+    }
+
+    worksheet
 }
 
-fn insert_note<'a>(
+fn set_header_footer<'a>(
     worksheet: &'a mut Worksheet,
-    row: u32,
-    col: u16,
-    text: String,
-    options: NoteOptions,
+    options: Vec<HeaderFooterOption>,
+    variants: &mut HeaderFooterVariants,
 ) -> Result<&'a mut Worksheet, XlsxError> {
-    let mut note = Note::new(&text);
+    let mut images = Vec::new();
 
-    if let Some(author) = options.author {
-        note = note.set_author(&author);
+    for opt in options {
+        match opt {
+            HeaderFooterOption::Header(header) => {
+                worksheet.set_header(check_header_footer_length(header)?);
+            }
+            HeaderFooterOption::Footer(footer) => {
+                worksheet.set_footer(check_header_footer_length(footer)?);
+            }
+            HeaderFooterOption::FirstHeader(header) => variants.first_header = Some(check_header_footer_length(header)?),
+            HeaderFooterOption::FirstFooter(footer) => variants.first_footer = Some(check_header_footer_length(footer)?),
+            HeaderFooterOption::EvenHeader(header) => variants.even_header = Some(check_header_footer_length(header)?),
+            HeaderFooterOption::EvenFooter(footer) => variants.even_footer = Some(check_header_footer_length(footer)?),
+            HeaderFooterOption::HeaderImage(position, binary) => {
+                images.push((true, position, Image::new_from_buffer(binary.as_slice())?));
+            }
+            HeaderFooterOption::HeaderImagePath(position, path) => images.push((true, position, Image::new(path)?)),
+            HeaderFooterOption::FooterImage(position, binary) => {
+                images.push((false, position, Image::new_from_buffer(binary.as_slice())?));
+            }
+            HeaderFooterOption::FooterImagePath(position, path) => images.push((false, position, Image::new(path)?)),
+            HeaderFooterOption::ScaleWithDoc(enable) => {
+                worksheet.set_header_footer_scale_with_doc(enable);
+            }
+            HeaderFooterOption::AlignWithMargins(enable) => {
+                variants.align_with_margins = Some(enable);
+                worksheet.set_header_footer_align_with_page(enable);
+            }
+        }
     }
 
-    if let Some(visible) = options.visible {
-        note = note.set_visible(visible);
+    // Images are checked against the header/footer strings, so add them last
+    for (is_header, position, image) in images {
+        let position = match position {
+            HeaderFooterPosition::Left => HeaderImagePosition::Left,
+            HeaderFooterPosition::Center => HeaderImagePosition::Center,
+            HeaderFooterPosition::Right => HeaderImagePosition::Right,
+        };
+
+        if is_header {
+            worksheet.set_header_image(&image, position)?;
+        } else {
+            worksheet.set_footer_image(&image, position)?;
+        }
     }
 
-    if let Some(width) = options.width {
-        note = note.set_width(width);
+    // Make sure the <headerFooter> element is written even without an odd
+    // page header/footer, so the variants can be added to it after saving.
+    // Changing the alignment sets rust_xlsxwriter 0.95's private
+    // head_footer_changed flag, which is what writes the element; the crate is
+    // pinned to that version for this.
+    if !variants.is_empty() {
+        worksheet.set_header_footer_align_with_page(false);
+        worksheet.set_header_footer_align_with_page(variants.align_with_margins.unwrap_or(true));
     }
 
-    if let Some(height) = options.height {
-        note = note.set_height(height);
+    Ok(worksheet)
+}
+
+// rust_xlsxwriter only warns about over-long headers/footers and drops them.
+fn check_header_footer_length(text: String) -> Result<String, XlsxError> {
+    if expand_header_footer_codes(&text).chars().count() > 255 {
+        return Err(XlsxError::ParameterError(format!(
+            "Header/footer '{text}' exceeds Excel's limit of 255 characters."
+        )));
     }
 
-    worksheet.insert_note(row, col, &note)
+    Ok(text)
+}
+
+// Expand the &[Page] style aliases rust_xlsxwriter accepts to Excel's codes.
+fn expand_header_footer_codes(text: &str) -> String {
+    text.replace("&[Tab]", "&A")
+        .replace("&[Date]", "&D")
+        .replace("&[File]", "&F")
+        .replace("&[Page]", "&P")
+        .replace("&[Path]", "&Z")
+        .replace("&[Time]", "&T")
+        .replace("&[Pages]", "&N")
+        .replace("&[Picture]", "&G")
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn add_table<'a>(
+    worksheet: &'a mut Worksheet,
+    format_cache: &mut FormatCache,
+    first_row: u32,
+    first_col: u16,
+    last_row: u32,
+    last_col: u16,
+    options: Vec<TableOption>,
+) -> Result<&'a mut Worksheet, XlsxError> {
+    let mut table = Table::new();
+
+    for opt in options {
+        table = match opt {
+            TableOption::Name(name) => table.set_name(name),
+            TableOption::Style(style) => table.set_style(convert_table_style(style)?),
+            TableOption::HeaderRow(enable) => table.set_header_row(enable),
+            TableOption::TotalRow(enable) => table.set_total_row(enable),
+            TableOption::BandedRows(enable) => table.set_banded_rows(enable),
+            TableOption::BandedColumns(enable) => table.set_banded_columns(enable),
+            TableOption::FirstColumn(enable) => table.set_first_column(enable),
+            TableOption::LastColumn(enable) => table.set_last_column(enable),
+            TableOption::Autofilter(enable) => table.set_autofilter(enable),
+            TableOption::Columns(columns) => {
+                let columns: Vec<TableColumn> =
+                    columns.into_iter().map(|column| build_table_column(format_cache, column)).collect();
+                table.set_columns(&columns)
+            }
+        };
+    }
+
+    worksheet.add_table(first_row, first_col, last_row, last_col, &table)
+}
+
+fn build_table_column(format_cache: &mut FormatCache, options: Vec<TableColumnOption>) -> TableColumn {
+    let mut column = TableColumn::new();
+
+    for opt in options {
+        column = match opt {
+            TableColumnOption::Header(header) => column.set_header(header),
+            TableColumnOption::TotalFunction(function) => column.set_total_function(match function {
+                TotalFunction::Sum => TableFunction::Sum,
+                TotalFunction::Average => TableFunction::Average,
+                TotalFunction::Count => TableFunction::Count,
+                TotalFunction::CountNumbers => TableFunction::CountNumbers,
+                TotalFunction::Max => TableFunction::Max,
+                TotalFunction::Min => TableFunction::Min,
+                TotalFunction::StdDev => TableFunction::StdDev,
+                TotalFunction::Var => TableFunction::Var,
+                TotalFunction::Custom(formula) => TableFunction::Custom(Formula::new(formula)),
+            }),
+            TableColumnOption::TotalLabel(label) => column.set_total_label(label),
+            TableColumnOption::Formula(formula) => column.set_formula(Formula::new(formula)),
+            TableColumnOption::Format(formats) => column.set_format(format_cache.get(None, formats)),
+            TableColumnOption::HeaderFormat(formats) => column.set_header_format(format_cache.get(None, formats)),
+        };
+    }
+
+    column
 }
 
+#[allow(clippy::question_mark)]
 fn merge_range<'a, 'b>(
     worksheet: &'a mut Worksheet,
+    format_cache: &mut FormatCache,
     first_row: u32,
     first_col: u16,
     last_row: u32,
@@ -322,14 +3130,14 @@ fn merge_range<'a, 'b>(
     match data {
         CellData::String(val) => worksheet.merge_range(first_row, first_col, last_row, last_col, &val, &Format::new()),
         CellData::StringWithFormat(val, formats) => {
-            let format = apply_formats(Format::new(), &formats);
-            worksheet.merge_range(first_row, first_col, last_row, last_col, &val, &format)
+            let format = format_cache.get(None, formats);
+            worksheet.merge_range(first_row, first_col, last_row, last_col, &val, format)
         }
         CellData::NumberWithFormat(val, formats) => {
-            let format = apply_formats(Format::new(), &formats);
+            let format = format_cache.get(None, formats);
             // Write value to first cell, then merge the range with the same format
-            worksheet.write_number_with_format(first_row, first_col, val, &format)?;
-            worksheet.merge_range(first_row, first_col, last_row, last_col, "", &format)
+            worksheet.write_number_with_format(first_row, first_col, val, format)?;
+            worksheet.merge_range(first_row, first_col, last_row, last_col, "", format)
         }
         CellData::Float(val) => {
             // Write number to first cell, then merge
@@ -342,47 +3150,81 @@ fn merge_range<'a, 'b>(
             worksheet.merge_range(first_row, first_col, last_row, last_col, "", &Format::new())
         }
         CellData::BooleanWithFormat(val, formats) => {
-            let format = apply_formats(Format::new(), &formats);
-            worksheet.write_boolean_with_format(first_row, first_col, val, &format)?;
-            worksheet.merge_range(first_row, first_col, last_row, last_col, "", &format)
+            let format = format_cache.get(None, formats);
+            worksheet.write_boolean_with_format(first_row, first_col, val, format)?;
+            worksheet.merge_range(first_row, first_col, last_row, last_col, "", format)
         }
         CellData::Blank(formats) => {
-            let format = apply_formats(Format::new(), &formats);
-            worksheet.merge_range(first_row, first_col, last_row, last_col, "", &format)
+            let format = format_cache.get(None, formats);
+            worksheet.merge_range(first_row, first_col, last_row, last_col, "", format)
         }
         CellData::DateWithFormat(iso8601, user_formats) => {
-            let date_format = apply_formats(
-                Format::new().set_num_format("yyyy-mm-dd"),
-                &user_formats,
-            );
+            let date_format = format_cache.get(Some(DATE_FORMAT), user_formats);
 
             let date = match ExcelDateTime::parse_from_str(&iso8601) {
                 Err(e) => return Err(e),
                 Ok(d) => d,
             };
-            worksheet.write_with_format(first_row, first_col, &date, &date_format)?;
-            worksheet.merge_range(first_row, first_col, last_row, last_col, "", &date_format)
+            worksheet.write_with_format(first_row, first_col, &date, date_format)?;
+            worksheet.merge_range(first_row, first_col, last_row, last_col, "", date_format)
         }
         CellData::DateTimeWithFormat(iso8601, user_formats) => {
-            let date_format = apply_formats(
-                Format::new().set_num_format("yyyy-mm-ddThh:mm:ss"),
-                &user_formats,
-            );
+            let date_format = format_cache.get(Some(DATETIME_FORMAT), user_formats);
+
+            let date = match ExcelDateTime::parse_from_str(&iso8601) {
+                Err(e) => return Err(e),
+                Ok(d) => d,
+            };
+            worksheet.write_with_format(first_row, first_col, &date, date_format)?;
+            worksheet.merge_range(first_row, first_col, last_row, last_col, "", date_format)
+        }
+        CellData::StringWithStyle(val, style) => {
+            let format = format_cache.get_style(None, style)?;
+            worksheet.merge_range(first_row, first_col, last_row, last_col, &val, format)
+        }
+        CellData::NumberWithStyle(val, style) => {
+            let format = format_cache.get_style(None, style)?;
+            worksheet.write_number_with_format(first_row, first_col, val, format)?;
+            worksheet.merge_range(first_row, first_col, last_row, last_col, "", format)
+        }
+        CellData::BooleanWithStyle(val, style) => {
+            let format = format_cache.get_style(None, style)?;
+            worksheet.write_boolean_with_format(first_row, first_col, val, format)?;
+            worksheet.merge_range(first_row, first_col, last_row, last_col, "", format)
+        }
+        CellData::BlankWithStyle(style) => {
+            let format = format_cache.get_style(None, style)?;
+            worksheet.merge_range(first_row, first_col, last_row, last_col, "", format)
+        }
+        CellData::DateWithStyle(iso8601, style) => {
+            let date_format = format_cache.get_style(Some(DATE_FORMAT), style)?;
+
+            let date = match ExcelDateTime::parse_from_str(&iso8601) {
+                Err(e) => return Err(e),
+                Ok(d) => d,
+            };
+            worksheet.write_with_format(first_row, first_col, &date, date_format)?;
+            worksheet.merge_range(first_row, first_col, last_row, last_col, "", date_format)
+        }
+        CellData::DateTimeWithStyle(iso8601, style) => {
+            let date_format = format_cache.get_style(Some(DATETIME_FORMAT), style)?;
 
             let date = match ExcelDateTime::parse_from_str(&iso8601) {
                 Err(e) => return Err(e),
                 Ok(d) => d,
             };
-            worksheet.write_with_format(first_row, first_col, &date, &date_format)?;
-            worksheet.merge_range(first_row, first_col, last_row, last_col, "", &date_format)
+            worksheet.write_with_format(first_row, first_col, &date, date_format)?;
+            worksheet.merge_range(first_row, first_col, last_row, last_col, "", date_format)
         }
         // For other types that don't support merge_range, write to first cell only
-        _ => write_data(worksheet, first_row, first_col, data),
+        _ => write_data(worksheet, format_cache, first_row, first_col, data),
     }
 }
 
+#[allow(clippy::needless_return)]
 fn write_data<'a, 'b>(
     worksheet: &'a mut Worksheet,
+    format_cache: &mut FormatCache,
     row: u32,
     col: u16,
     data: CellData<'b>,
@@ -390,62 +3232,56 @@ fn write_data<'a, 'b>(
     match data {
         CellData::String(val) => worksheet.write(row, col, val),
         CellData::StringWithFormat(val, formats) => {
-            let format = apply_formats(Format::new(), &formats);
-            worksheet.write_with_format(row, col, val, &format)
+            let format = format_cache.get(None, formats);
+            worksheet.write_with_format(row, col, val, format)
         }
         CellData::NumberWithFormat(val, formats) => {
-            let format = apply_formats(Format::new(), &formats);
-            worksheet.write_number_with_format(row, col, val, &format)
+            let format = format_cache.get(None, formats);
+            worksheet.write_number_with_format(row, col, val, format)
         }
 
         CellData::Float(val) => worksheet.write(row, col, val),
         CellData::Date(iso8601) => {
-            let date_format = Format::new().set_num_format("yyyy-mm-dd");
+            let date_format = format_cache.get(Some(DATE_FORMAT), Vec::new());
 
             match ExcelDateTime::parse_from_str(&iso8601) {
                 Err(e) => return Err(e),
-                Ok(date) => worksheet.write_with_format(row, col, &date, &date_format),
+                Ok(date) => worksheet.write_with_format(row, col, &date, date_format),
             }
         },
         CellData::DateWithFormat(iso8601, user_formats) => {
-            let date_format = apply_formats(
-                Format::new().set_num_format("yyyy-mm-dd"),
-                &user_formats,
-            );
+            let date_format = format_cache.get(Some(DATE_FORMAT), user_formats);
 
             match ExcelDateTime::parse_from_str(&iso8601) {
                 Err(e) => return Err(e),
-                Ok(date) => worksheet.write_with_format(row, col, &date, &date_format),
+                Ok(date) => worksheet.write_with_format(row, col, &date, date_format),
             }
         },
         CellData::DateTime(iso8601) => {
-            let date_format = Format::new().set_num_format("yyyy-mm-ddThh:mm:ss");
+            let date_format = format_cache.get(Some(DATETIME_FORMAT), Vec::new());
 
             match ExcelDateTime::parse_from_str(&iso8601) {
                 Err(e) => return Err(e),
-                Ok(date) => worksheet.write_with_format(row, col, &date, &date_format),
+                Ok(date) => worksheet.write_with_format(row, col, &date, date_format),
             }
         },
         CellData::DateTimeWithFormat(iso8601, user_formats) => {
-            let date_format = apply_formats(
-                Format::new().set_num_format("yyyy-mm-ddThh:mm:ss"),
-                &user_formats,
-            );
+            let date_format = format_cache.get(Some(DATETIME_FORMAT), user_formats);
 
             match ExcelDateTime::parse_from_str(&iso8601) {
                 Err(e) => return Err(e),
-                Ok(date) => worksheet.write_with_format(row, col, &date, &date_format),
+                Ok(date) => worksheet.write_with_format(row, col, &date, date_format),
             }
         },
         CellData::Formula(val) => worksheet.write(row, col, Formula::new(val)),
         CellData::FormulaWithFormat(val, formats) => {
-            let format = apply_formats(Format::new(), &formats);
-            worksheet.write_with_format(row, col, Formula::new(val), &format)
+            let format = format_cache.get(None, formats);
+            worksheet.write_with_format(row, col, Formula::new(val), format)
         }
         CellData::Boolean(val) => worksheet.write_boolean(row, col, val),
         CellData::BooleanWithFormat(val, formats) => {
-            let format = apply_formats(Format::new(), &formats);
-            worksheet.write_boolean_with_format(row, col, val, &format)
+            let format = format_cache.get(None, formats);
+            worksheet.write_boolean_with_format(row, col, val, format)
         }
         CellData::Url(url) => {
             let url_obj = Url::new(&url);
@@ -456,18 +3292,18 @@ fn write_data<'a, 'b>(
             worksheet.write_url_with_text(row, col, &url_obj, &text)
         }
         CellData::UrlWithFormat(url, formats) => {
-            let format = apply_formats(Format::new(), &formats);
+            let format = format_cache.get(None, formats);
             let url_obj = Url::new(&url);
-            worksheet.write_url_with_format(row, col, &url_obj, &format)
+            worksheet.write_url_with_format(row, col, &url_obj, format)
         }
         CellData::UrlWithTextAndFormat(url, text, formats) => {
-            let format = apply_formats(Format::new(), &formats);
+            let format = format_cache.get(None, formats);
             let url_obj = Url::new(&url).set_text(&text);
-            worksheet.write_url_with_format(row, col, &url_obj, &format)
+            worksheet.write_url_with_format(row, col, &url_obj, format)
         }
         CellData::Blank(formats) => {
-            let format = apply_formats(Format::new(), &formats);
-            worksheet.write_blank(row, col, &format)
+            let format = format_cache.get(None, formats);
+            worksheet.write_blank(row, col, format)
         }
         CellData::ImagePath(val) => match Image::new(val) {
             Err(e) => return Err(e),
@@ -478,41 +3314,152 @@ fn write_data<'a, 'b>(
             Ok(image) => worksheet.insert_image(row, col, &image),
         },
         CellData::RichString(segments) => {
-            write_rich_string_helper(worksheet, row, col, &segments, None)
+            write_rich_string_helper(worksheet, format_cache, row, col, segments, None)
         }
         CellData::RichStringWithFormat(segments, cell_formats) => {
-            let cell_format = apply_formats(Format::new(), &cell_formats);
-            write_rich_string_helper(worksheet, row, col, &segments, Some(cell_format))
+            write_rich_string_helper(worksheet, format_cache, row, col, segments, Some(cell_formats))
+        }
+        CellData::StringWithStyle(val, style) => {
+            let format = format_cache.get_style(None, style)?;
+            worksheet.write_with_format(row, col, val, format)
+        }
+        CellData::NumberWithStyle(val, style) => {
+            let format = format_cache.get_style(None, style)?;
+            worksheet.write_number_with_format(row, col, val, format)
+        }
+        CellData::DateWithStyle(iso8601, style) => {
+            let date_format = format_cache.get_style(Some(DATE_FORMAT), style)?;
+            let date = ExcelDateTime::parse_from_str(&iso8601)?;
+            worksheet.write_with_format(row, col, &date, date_format)
+        }
+        CellData::DateTimeWithStyle(iso8601, style) => {
+            let date_format = format_cache.get_style(Some(DATETIME_FORMAT), style)?;
+            let date = ExcelDateTime::parse_from_str(&iso8601)?;
+            worksheet.write_with_format(row, col, &date, date_format)
+        }
+        CellData::FormulaWithStyle(val, style) => {
+            let format = format_cache.get_style(None, style)?;
+            worksheet.write_with_format(row, col, Formula::new(val), format)
+        }
+        CellData::BooleanWithStyle(val, style) => {
+            let format = format_cache.get_style(None, style)?;
+            worksheet.write_boolean_with_format(row, col, val, format)
+        }
+        CellData::BlankWithStyle(style) => {
+            let format = format_cache.get_style(None, style)?;
+            worksheet.write_blank(row, col, format)
         }
     }
 }
 
 fn write_rich_string_helper<'a>(
     worksheet: &'a mut Worksheet,
+    format_cache: &mut FormatCache,
     row: u32,
     col: u16,
-    segments: &[(String, Vec<CellFormat>)],
-    cell_format: Option<Format>,
+    segments: Vec<(String, Vec<CellFormat>)>,
+    cell_formats: Option<Vec<CellFormat>>,
 ) -> Result<&'a mut Worksheet, XlsxError> {
     // Build format objects for each segment
-    let segment_formats: Vec<Format> = segments
-        .iter()
-        .map(|(_, formats)| apply_formats(Format::new(), formats))
-        .collect();
+    let (texts, segment_formats): (Vec<String>, Vec<Format>) = segments
+        .into_iter()
+        .map(|(text, formats)| (text, format_cache.get(None, formats).clone()))
+        .unzip();
 
     // Build the segments array with references
-    let rich_segments: Vec<(&Format, &str)> = segments
+    let rich_segments: Vec<(&Format, &str)> = segment_formats
         .iter()
-        .zip(segment_formats.iter())
-        .map(|((text, _), format)| (format, text.as_str()))
+        .zip(texts.iter())
+        .map(|(format, text)| (format, text.as_str()))
         .collect();
 
-    match cell_format {
-        Some(format) => worksheet.write_rich_string_with_format(row, col, &rich_segments, &format),
+    match cell_formats {
+        Some(formats) => {
+            let format = format_cache.get(None, formats);
+            worksheet.write_rich_string_with_format(row, col, &rich_segments, format)
+        }
         None => worksheet.write_rich_string(row, col, &rich_segments),
     }
 }
 
+const DATE_FORMAT: &str = "yyyy-mm-dd";
+const DATETIME_FORMAT: &str = "yyyy-mm-ddThh:mm:ss";
+
+// Formats built for cell writes, keyed by the default number format and the
+// format list, so cells sharing a format list reuse one Format instead of
+// building and hashing a new one each. Also holds the workbook style table,
+// whose formats are built once per default number format.
+struct FormatCache {
+    formats: HashMap<(Option<&'static str>, Vec<CellFormat>), Format>,
+    styles: Vec<Vec<CellFormat>>,
+    style_indices: HashMap<String, usize>,
+    style_formats: HashMap<(Option<&'static str>, usize), Format>,
+}
+
+impl FormatCache {
+    fn new(styles: Vec<(String, Vec<CellFormat>)>) -> Result<Self, String> {
+        let mut style_indices = HashMap::new();
+        let mut style_lists = Vec::new();
+
+        for (index, (name, formats)) in styles.into_iter().enumerate() {
+            if name.is_empty() {
+                return Err("Style name must not be empty".to_string());
+            }
+
+            if style_indices.contains_key(&name) {
+                return Err(format!("Style '{name}' is defined more than once"));
+            }
+
+            style_indices.insert(name, index);
+            style_lists.push(formats);
+        }
+
+        Ok(FormatCache {
+            formats: HashMap::new(),
+            styles: style_lists,
+            style_indices,
+            style_formats: HashMap::new(),
+        })
+    }
+
+    fn get_style(&mut self, num_format: Option<&'static str>, style: StyleRef) -> Result<&Format, XlsxError> {
+        let index = match style {
+            StyleRef::Index(index) if index < self.styles.len() => index,
+            StyleRef::Index(index) => {
+                return Err(XlsxError::ParameterError(format!("Style index {index} is out of range.")))
+            }
+            StyleRef::Name(name) => match self.style_indices.get(&name) {
+                Some(index) => *index,
+                None => return Err(XlsxError::ParameterError(format!("Style '{name}' is not defined."))),
+            },
+        };
+
+        let styles = &self.styles;
+
+        Ok(self.style_formats.entry((num_format, index)).or_insert_with(|| {
+            let format = match num_format {
+                Some(num_format) => Format::new().set_num_format(num_format),
+                None => Format::new(),
+            };
+
+            apply_formats(format, &styles[index])
+        }))
+    }
+
+    fn get(&mut self, num_format: Option<&'static str>, formats: Vec<CellFormat>) -> &Format {
+        self.formats
+            .entry((num_format, formats))
+            .or_insert_with_key(|(num_format, formats)| {
+                let format = match num_format {
+                    Some(num_format) => Format::new().set_num_format(*num_format),
+                    None => Format::new(),
+                };
+
+                apply_formats(format, formats)
+            })
+    }
+}
+
 fn apply_formats(mut format: Format, formats: &[CellFormat]) -> Format {
     for fmt in formats {
         format = match fmt {
@@ -621,9 +3568,12 @@ fn apply_formats(mut format: Format, formats: &[CellFormat]) -> Format {
             CellFormat::Rotation(angle) => format.set_rotation(*angle),
             CellFormat::Shrink => format.set_shrink(),
             CellFormat::Indent(level) => format.set_indent(*level),
+            CellFormat::Locked => format.set_locked(),
+            CellFormat::Unlocked => format.set_unlocked(),
+            CellFormat::Hidden => format.set_hidden(),
         };
     }
-    return format;
+    format
 }
 
 /// Parses a hex color string (e.g., "#FF0000" or "FF0000") into a Color.
@@ -635,6 +3585,60 @@ fn parse_hex_color(color_hex: &str) -> Option<Color> {
         .map(Color::from)
 }
 
+fn convert_chart_kind(kind: &ChartKind) -> ChartType {
+    match kind {
+        ChartKind::Area => ChartType::Area,
+        ChartKind::AreaStacked => ChartType::AreaStacked,
+        ChartKind::AreaPercentStacked => ChartType::AreaPercentStacked,
+        ChartKind::Bar => ChartType::Bar,
+        ChartKind::BarStacked => ChartType::BarStacked,
+        ChartKind::BarPercentStacked => ChartType::BarPercentStacked,
+        ChartKind::Column => ChartType::Column,
+        ChartKind::ColumnStacked => ChartType::ColumnStacked,
+        ChartKind::ColumnPercentStacked => ChartType::ColumnPercentStacked,
+        ChartKind::Doughnut => ChartType::Doughnut,
+        ChartKind::Line => ChartType::Line,
+        ChartKind::LineStacked => ChartType::LineStacked,
+        ChartKind::LinePercentStacked => ChartType::LinePercentStacked,
+        ChartKind::Pie => ChartType::Pie,
+        ChartKind::Radar => ChartType::Radar,
+        ChartKind::RadarWithMarkers => ChartType::RadarWithMarkers,
+        ChartKind::RadarFilled => ChartType::RadarFilled,
+        ChartKind::Scatter => ChartType::Scatter,
+        ChartKind::ScatterStraight => ChartType::ScatterStraight,
+        ChartKind::ScatterStraightWithMarkers => ChartType::ScatterStraightWithMarkers,
+        ChartKind::ScatterSmooth => ChartType::ScatterSmooth,
+        ChartKind::ScatterSmoothWithMarkers => ChartType::ScatterSmoothWithMarkers,
+    }
+}
+
+fn convert_line_dash_style(style: &LineDashStyle) -> ChartLineDashType {
+    match style {
+        LineDashStyle::Solid => ChartLineDashType::Solid,
+        LineDashStyle::RoundDot => ChartLineDashType::RoundDot,
+        LineDashStyle::SquareDot => ChartLineDashType::SquareDot,
+        LineDashStyle::Dash => ChartLineDashType::Dash,
+        LineDashStyle::DashDot => ChartLineDashType::DashDot,
+        LineDashStyle::LongDash => ChartLineDashType::LongDash,
+        LineDashStyle::LongDashDot => ChartLineDashType::LongDashDot,
+        LineDashStyle::LongDashDotDot => ChartLineDashType::LongDashDotDot,
+    }
+}
+
+fn convert_data_label_position(position: &DataLabelPosition) -> ChartDataLabelPosition {
+    match position {
+        DataLabelPosition::Center => ChartDataLabelPosition::Center,
+        DataLabelPosition::Right => ChartDataLabelPosition::Right,
+        DataLabelPosition::Left => ChartDataLabelPosition::Left,
+        DataLabelPosition::Above => ChartDataLabelPosition::Above,
+        DataLabelPosition::Below => ChartDataLabelPosition::Below,
+        DataLabelPosition::InsideBase => ChartDataLabelPosition::InsideBase,
+        DataLabelPosition::InsideEnd => ChartDataLabelPosition::InsideEnd,
+        DataLabelPosition::OutsideEnd => ChartDataLabelPosition::OutsideEnd,
+        DataLabelPosition::BestFit => ChartDataLabelPosition::BestFit,
+    }
+}
+
 fn convert_border_style(style: &BorderStyle) -> FormatBorder {
     match style {
         BorderStyle::Thin => FormatBorder::Thin,
@@ -654,3 +3658,151 @@ fn convert_border_style(style: &BorderStyle) -> FormatBorder {
 }
 
 rustler::init!("Elixir.XlsxWriter.RustXlsxWriter");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn options() -> WorkbookOptions {
+        WorkbookOptions {
+            defined_names: vec![],
+            protect_structure: false,
+            structure_password: None,
+            read_only_recommended: false,
+            password: None,
+            memory_mode: MemoryMode::Standard,
+            tempdir: None,
+            styles: vec![],
+        }
+    }
+
+    fn sheet_xml(sheet: Vec<Sheet>) -> String {
+        let buf = write_impl(vec![("Sheet1".to_string(), sheet)], None, options()).unwrap();
+        let mut zip = ZipArchive::new(Cursor::new(buf)).unwrap();
+        let mut xml = String::new();
+        zip.by_name("xl/worksheets/sheet1.xml").unwrap().read_to_string(&mut xml).unwrap();
+        xml
+    }
+
+    // Pins the numbering copied from rust_xlsxwriter 0.95 in
+    // conditional_format_priorities: classic rules across several ranges
+    // plus x14-only icon sets, with and without a requested priority.
+    #[test]
+    fn conditional_format_priorities_cover_ranges_and_x14_rules() {
+        use ConditionalFormatOption::{Format, Priority};
+
+        let xml = sheet_xml(vec![
+            Sheet::AddConditionalFormat(
+                0,
+                2,
+                9,
+                2,
+                ConditionalRule::Cell(CellRule::GreaterThan(ConditionalValue::Number(5.0))),
+                vec![Format(vec![CellFormat::Bold]), Priority(4)],
+            ),
+            Sheet::AddConditionalFormat(0, 0, 9, 0, ConditionalRule::IconSet(IconStyle::ThreeStars, vec![]), vec![Priority(1)]),
+            Sheet::AddConditionalFormat(0, 2, 9, 2, ConditionalRule::DataBar(vec![]), vec![Priority(2)]),
+            Sheet::AddConditionalFormat(0, 1, 9, 1, ConditionalRule::IconSet(IconStyle::FiveBoxes, vec![]), vec![]),
+            Sheet::AddConditionalFormat(0, 0, 9, 0, ConditionalRule::Blanks, vec![Format(vec![CellFormat::Italic]), Priority(3)]),
+        ]);
+
+        assert!(xml.contains(r#"<conditionalFormatting sqref="C1:C10"><cfRule type="cellIs" dxfId="0" priority="4" "#));
+        assert!(xml.contains(r#"<cfRule type="dataBar" priority="2">"#));
+        assert!(xml.contains(r#"<conditionalFormatting sqref="A1:A10"><cfRule type="containsBlanks" dxfId="1" priority="3">"#));
+        assert!(xml.contains(r#"<x14:cfRule type="iconSet" priority="1" id="{DA7ABA51-AAAA-BBBB-0001-000000000001}"><x14:iconSet iconSet="3Stars">"#));
+        assert!(xml.contains(r#"<x14:cfRule type="iconSet" priority="5" id="{DA7ABA51-AAAA-BBBB-0001-000000000002}"><x14:iconSet iconSet="5Boxes">"#));
+    }
+
+    #[test]
+    fn rewrite_priorities_rejects_malformed_rules() {
+        let priorities = HashMap::from([(1, 2)]);
+
+        let unterminated_tag = rewrite_priorities("sheet1.xml", r#"<cfRule type="blanks" priority="1""#, "<cfRule ", &priorities);
+        assert!(unterminated_tag.is_err());
+
+        let unterminated_attribute = rewrite_priorities("sheet1.xml", r#"<cfRule type="blanks" priority="1>"#, "<cfRule ", &priorities);
+        assert!(unterminated_attribute.is_err());
+    }
+
+    // The <headerFooter> element is forced out through a private flag of
+    // rust_xlsxwriter 0.95, see set_header_footer.
+    #[test]
+    fn header_footer_variants_without_a_default_header() {
+        let xml = sheet_xml(vec![Sheet::SetHeaderFooter(vec![
+            HeaderFooterOption::FirstHeader("&CCover".to_string()),
+            HeaderFooterOption::EvenFooter("&LPage &P".to_string()),
+        ])]);
+
+        assert!(xml.contains(concat!(
+            r#"<headerFooter differentOddEven="1" differentFirst="1">"#,
+            "<evenFooter>&amp;LPage &amp;P</evenFooter><firstHeader>&amp;CCover</firstHeader></headerFooter>",
+        )));
+    }
+
+    #[test]
+    fn outline_ranges_group_each_level_once() {
+        let group = |first, last, level, collapsed| OutlineGroup { first, last, level, collapsed };
+        let groups = [
+            group(1, 8, 1, false),
+            group(1, 3, 2, false),
+            group(4, 4, 2, false),
+            group(5, 7, 2, true),
+            group(6, 6, 4, false),
+        ];
+
+        assert_eq!(
+            outline_ranges(&groups),
+            vec![
+                (1, 8, false),
+                (1, 4, false),
+                (5, 7, true),
+                (6, 6, false),
+                (6, 6, false),
+            ]
+        );
+    }
+
+    fn shared_formats() -> Vec<CellFormat> {
+        vec![CellFormat::Bold, CellFormat::Align(CellAlignPos::Right), CellFormat::NumFormat("#,##0.00".to_string())]
+    }
+
+    // 1M formatted cells sharing one format list, with a Format built per
+    // cell as write_data did before the cache, and through the cache.
+    #[test]
+    #[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+    fn format_cache_benchmark() {
+        const ROWS: u32 = 100_000;
+        const COLS: u16 = 10;
+
+        fn write_cells(mut write_cell: impl FnMut(&mut Worksheet, u32, u16)) -> Duration {
+            let start = Instant::now();
+            let mut workbook = Workbook::new();
+            let worksheet = workbook.add_worksheet();
+            for row in 0..ROWS {
+                for col in 0..COLS {
+                    write_cell(worksheet, row, col);
+                }
+            }
+            start.elapsed()
+        }
+
+        // Alternate the two and keep the best of each to smooth out noise
+        let mut uncached = Duration::MAX;
+        let mut cached = Duration::MAX;
+        for _ in 0..5 {
+            uncached = uncached.min(write_cells(|worksheet, row, col| {
+                let format = apply_formats(Format::new(), &shared_formats());
+                worksheet.write_number_with_format(row, col, row as f64, &format).unwrap();
+            }));
+
+            let mut format_cache = FormatCache::new(vec![]).unwrap();
+            cached = cached.min(write_cells(|worksheet, row, col| {
+                let format = format_cache.get(None, shared_formats());
+                worksheet.write_number_with_format(row, col, row as f64, format).unwrap();
+            }));
+        }
+
+        println!("1M formatted cells, uncached: {uncached:?}, cached: {cached:?}");
+    }
+}
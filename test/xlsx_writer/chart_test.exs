@@ -0,0 +1,429 @@
+defmodule XlsxWriter.ChartTest do
+  use ExUnit.Case, async: true
+
+  alias XlsxWriter
+
+  defp data_sheet(name) do
+    XlsxWriter.new_sheet(name)
+    |> XlsxWriter.write(0, 0, "Month")
+    |> XlsxWriter.write(0, 1, "Revenue")
+    |> XlsxWriter.write(0, 2, "Costs")
+    |> XlsxWriter.write(1, 0, "Jan")
+    |> XlsxWriter.write(1, 1, 100)
+    |> XlsxWriter.write(1, 2, 80)
+    |> XlsxWriter.write(2, 0, "Feb")
+    |> XlsxWriter.write(2, 1, 120)
+    |> XlsxWriter.write(2, 2, 90)
+    |> XlsxWriter.write(3, 0, "Mar")
+    |> XlsxWriter.write(3, 1, 140)
+    |> XlsxWriter.write(3, 2, 95)
+  end
+
+  defp part(content, path) do
+    {:ok, files} = :zip.unzip(content, [:memory])
+    {_, xml} = List.keyfind(files, String.to_charlist(path), 0)
+    xml
+  end
+
+  describe "insert_chart/6" do
+    test "creates chart instruction with series and options" do
+      sheet =
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.insert_chart(0, 4, :line, [[values: "Test!$B$2:$B$4"]],
+          title: "Trend",
+          legend: :bottom,
+          size: {640, 320}
+        )
+
+      assert {"Test",
+              [
+                {:insert_chart, 0, 4, :line, [[values: "Test!$B$2:$B$4"]],
+                 [{:title, "Trend"}, {:legend, :bottom}, {:size, 640, 320}]}
+              ]} = sheet
+    end
+
+    test "writes every chart type with its series ranges" do
+      for {type, plot, categories, values} <- [
+            {:column, ~s(<c:barChart><c:barDir val="col"/>), "c:cat", "c:val"},
+            {:bar, ~s(<c:barChart><c:barDir val="bar"/>), "c:cat", "c:val"},
+            {:line, "<c:lineChart>", "c:cat", "c:val"},
+            {:area, "<c:areaChart>", "c:cat", "c:val"},
+            {:pie, "<c:pieChart>", "c:cat", "c:val"},
+            {:doughnut, "<c:doughnutChart>", "c:cat", "c:val"},
+            {:scatter, "<c:scatterChart>", "c:xVal", "c:yVal"},
+            {:radar, "<c:radarChart>", "c:cat", "c:val"}
+          ] do
+        sheet =
+          data_sheet("Data")
+          |> XlsxWriter.insert_chart(5, 0, type, [
+            [
+              name: "Revenue",
+              categories: "Data!$A$2:$A$4",
+              values: "Data!$B$2:$B$4"
+            ]
+          ])
+
+        assert {:ok, content} = XlsxWriter.generate([sheet])
+        chart = part(content, "xl/charts/chart1.xml")
+
+        assert chart =~ plot
+        assert chart =~ "<c:tx><c:v>Revenue</c:v></c:tx>"
+        assert chart =~ "<#{categories}><c:numRef><c:f>Data!$A$2:$A$4</c:f>"
+        assert chart =~ "<#{values}><c:numRef><c:f>Data!$B$2:$B$4</c:f>"
+      end
+    end
+
+    test "links the chart to the sheet through a drawing" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.insert_chart(5, 0, :column, [[values: "Data!$B$2:$B$4"]])
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert part(content, "xl/worksheets/sheet1.xml") =~ ~s(<drawing r:id="rId1"/>)
+
+      assert part(content, "xl/worksheets/_rels/sheet1.xml.rels") =~
+               ~s(relationships/drawing" Target="../drawings/drawing1.xml"/>)
+
+      assert part(content, "xl/drawings/drawing1.xml") =~
+               "<xdr:from><xdr:col>0</xdr:col><xdr:colOff>0</xdr:colOff>" <>
+                 "<xdr:row>5</xdr:row><xdr:rowOff>0</xdr:rowOff></xdr:from>"
+
+      assert part(content, "xl/drawings/_rels/drawing1.xml.rels") =~
+               ~s(relationships/chart" Target="../charts/chart1.xml"/>)
+    end
+
+    test "generates valid xlsx with titles, legend, offset and size" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.insert_chart(
+          5,
+          0,
+          :column_stacked,
+          [
+            [name: "Revenue", categories: "Data!$A$2:$A$4", values: "Data!$B$2:$B$4"],
+            [name: "Costs", categories: "Data!$A$2:$A$4", values: "Data!$C$2:$C$4"]
+          ],
+          title: "Revenue vs Costs",
+          x_axis: [title: "Month"],
+          y_axis: [title: "EUR"],
+          legend: :none,
+          offset: {15, 10},
+          size: {720, 400}
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+      chart = part(content, "xl/charts/chart1.xml")
+
+      assert chart =~ ~s(<c:barDir val="col"/><c:grouping val="stacked"/>)
+      assert chart =~ ~s(<c:overlap val="100"/>)
+      assert chart =~ "<c:f>Data!$B$2:$B$4</c:f>"
+      assert chart =~ "<c:f>Data!$C$2:$C$4</c:f>"
+      assert chart =~ "<a:t>Revenue vs Costs</a:t>"
+      assert chart =~ ~r{<c:catAx>.*<a:t>Month</a:t>.*</c:catAx>}
+      assert chart =~ ~r{<c:valAx>.*<a:t>EUR</a:t>.*</c:valAx>}
+      refute chart =~ "<c:legend>"
+
+      # 15 x 10 pixels in EMUs from the anchor, 720 x 400 pixels wide
+      assert part(content, "xl/drawings/drawing1.xml") =~
+               "<xdr:from><xdr:col>0</xdr:col><xdr:colOff>142875</xdr:colOff>" <>
+                 "<xdr:row>5</xdr:row><xdr:rowOff>95250</xdr:rowOff></xdr:from>" <>
+                 "<xdr:to><xdr:col>11</xdr:col><xdr:colOff>295275</xdr:colOff>" <>
+                 "<xdr:row>25</xdr:row><xdr:rowOff>95250</xdr:rowOff></xdr:to>"
+    end
+
+    test "returns an error when a series has no values" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.insert_chart(5, 0, :column, [[name: "Empty"]])
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+  end
+
+  describe "chart series styling" do
+    test "normalizes trendline and error bar options into triples" do
+      sheet =
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.insert_chart(0, 4, :scatter, [
+          [
+            categories: "Test!$A$2:$A$4",
+            values: "Test!$B$2:$B$4",
+            trendline: :linear,
+            y_error_bars: {{:percentage, 5}, [direction: :plus]}
+          ]
+        ])
+
+      assert {"Test", [{:insert_chart, 0, 4, :scatter, [series], []}]} = sheet
+      assert {:trendline, :linear, []} in series
+      assert {:y_error_bars, {:percentage, 5}, [direction: :plus]} in series
+    end
+
+    test "writes colors, markers, labels, trendlines and error bars" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.insert_chart(5, 0, :line, [
+          [
+            name: "Revenue",
+            categories: "Data!$A$2:$A$4",
+            values: "Data!$B$2:$B$4",
+            line_color: "#1F4E79",
+            line_dash: :dash,
+            line_width: 2.25,
+            marker: [type: :diamond, size: 8, fill_color: "#FFC000", line_color: "#1F4E79"],
+            data_labels: [:value, position: :above, num_format: "#,##0"],
+            trendline:
+              {{:moving_average, 2},
+               [name: "2-month average", line_color: "#C00000", line_dash: :round_dot]},
+            y_error_bars: {:standard_error, [end_cap: false, line_color: "#7F7F7F"]}
+          ],
+          [
+            name: "Costs",
+            categories: "Data!$A$2:$A$4",
+            values: "Data!$C$2:$C$4",
+            marker: [type: :none],
+            trendline: {:linear, [:display_equation, :display_r_squared, forward_period: 1]}
+          ]
+        ])
+        |> XlsxWriter.insert_chart(5, 8, :pie, [
+          [
+            categories: "Data!$A$2:$A$4",
+            values: "Data!$B$2:$B$4",
+            data_labels: [:percentage, :category_name, :leader_lines, position: :best_fit]
+          ]
+        ])
+        |> XlsxWriter.insert_chart(20, 0, :bar, [
+          [
+            values: "Data!$B$2:$B$4",
+            fill_color: "#2E75B6",
+            line_color: "#000000",
+            x_error_bars: {{:fixed_value, 10}, []}
+          ]
+        ])
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      line = part(content, "xl/charts/chart1.xml")
+
+      assert line =~
+               ~s(<c:spPr><a:ln w="28575"><a:solidFill><a:srgbClr val="1F4E79"/></a:solidFill>) <>
+                 ~s(<a:prstDash val="dash"/></a:ln></c:spPr>)
+
+      assert line =~
+               ~s(<c:marker><c:symbol val="diamond"/><c:size val="8"/>) <>
+                 ~s(<c:spPr><a:solidFill><a:srgbClr val="FFC000"/></a:solidFill>)
+
+      assert line =~
+               ~s(<c:dLbls><c:numFmt formatCode="#,##0" sourceLinked="0"/>) <>
+                 ~s(<c:dLblPos val="t"/><c:showVal val="1"/></c:dLbls>)
+
+      assert line =~
+               ~s(<c:trendline><c:name>2-month average</c:name>) <>
+                 ~s(<c:spPr><a:ln><a:solidFill><a:srgbClr val="C00000"/></a:solidFill>) <>
+                 ~s(<a:prstDash val="sysDot"/></a:ln></c:spPr>) <>
+                 ~s(<c:trendlineType val="movingAvg"/><c:period val="2"/></c:trendline>)
+
+      assert line =~
+               ~s(<c:errBars><c:errDir val="y"/><c:errBarType val="both"/>) <>
+                 ~s(<c:errValType val="stdErr"/><c:noEndCap val="1"/>)
+
+      assert line =~ ~s(<c:marker><c:symbol val="none"/></c:marker>)
+
+      assert line =~
+               ~s(<c:trendlineType val="linear"/><c:forward val="1"/>) <>
+                 ~s(<c:dispRSqr val="1"/><c:dispEq val="1"/>)
+
+      # Best fit is the default position for pie labels, so it isn't written
+      assert part(content, "xl/charts/chart2.xml") =~
+               ~s(<c:dLbls><c:showCatName val="1"/><c:showPercent val="1"/>) <>
+                 ~s(<c:showLeaderLines val="1"/></c:dLbls>)
+
+      bar = part(content, "xl/charts/chart3.xml")
+
+      assert bar =~
+               ~s(<c:spPr><a:solidFill><a:srgbClr val="2E75B6"/></a:solidFill>) <>
+                 ~s(<a:ln><a:solidFill><a:srgbClr val="000000"/></a:solidFill></a:ln></c:spPr>)
+
+      assert bar =~
+               ~s(<c:errBars><c:errBarType val="both"/><c:errValType val="fixedVal"/>) <>
+                 ~s(<c:val val="10"/></c:errBars>)
+    end
+
+    test "returns an error for error bars the chart type can't show" do
+      column =
+        data_sheet("Data")
+        |> XlsxWriter.insert_chart(5, 0, :column, [
+          [values: "Data!$B$2:$B$4", x_error_bars: :standard_error]
+        ])
+
+      assert {:error, _reason} = XlsxWriter.generate([column])
+
+      bar =
+        data_sheet("Data")
+        |> XlsxWriter.insert_chart(5, 0, :bar, [
+          [values: "Data!$B$2:$B$4", y_error_bars: :standard_error]
+        ])
+
+      assert {:error, _reason} = XlsxWriter.generate([bar])
+    end
+
+    test "returns an error for an out-of-range polynomial order" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.insert_chart(5, 0, :line, [
+          [values: "Data!$B$2:$B$4", trendline: {:polynomial, 9}]
+        ])
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+  end
+
+  describe "combined charts and axes" do
+    test "normalizes the combined chart series" do
+      sheet =
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.insert_chart(0, 4, :column, [[values: "Test!$B$2:$B$4"]],
+          combine: {:line, [[values: "Test!$C$2:$C$4", trendline: :linear]]}
+        )
+
+      assert {"Test",
+              [
+                {:insert_chart, 0, 4, :column, _,
+                 [
+                   {:combine, :line,
+                    [[{:values, "Test!$C$2:$C$4"}, {:trendline, :linear, []}]]}
+                 ]}
+              ]} = sheet
+    end
+
+    test "writes a line on the secondary axis" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.write(0, 3, "Margin")
+        |> XlsxWriter.write(1, 3, 0.2)
+        |> XlsxWriter.write(2, 3, 0.25)
+        |> XlsxWriter.write(3, 3, 0.32)
+        |> XlsxWriter.insert_chart(
+          5,
+          0,
+          :column,
+          [[name: "Revenue", categories: "Data!$A$2:$A$4", values: "Data!$B$2:$B$4"]],
+          title: "Revenue and Margin",
+          y_axis: [title: "EUR", min: 0, max: 200, major_unit: 50, num_format: "#,##0"],
+          y2_axis: [title: "Margin", min: 0, max: 0.5, num_format: "0%"],
+          combine:
+            {:line,
+             [
+               [
+                 :secondary_axis,
+                 name: "Margin",
+                 categories: "Data!$A$2:$A$4",
+                 values: "Data!$D$2:$D$4",
+                 marker: [type: :circle]
+               ]
+             ]}
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+      chart = part(content, "xl/charts/chart1.xml")
+
+      # The line is a second chart in the same plot area with its own axes
+      assert chart =~
+               ~r{</c:barChart><c:lineChart>.*<c:tx><c:v>Margin</c:v></c:tx>.*<c:f>Data!\$D\$2:\$D\$4</c:f>.*</c:lineChart>}
+
+      assert chart =~
+               ~s(<c:valAx><c:axId val="50010002"/><c:scaling><c:orientation val="minMax"/>) <>
+                 ~s(<c:max val="200"/><c:min val="0"/></c:scaling><c:axPos val="l"/>)
+
+      assert chart =~ ~s(<c:numFmt formatCode="#,##0" sourceLinked="0"/>)
+      assert chart =~ ~s(<c:majorUnit val="50"/>)
+
+      assert chart =~
+               ~s(<c:valAx><c:axId val="60010002"/><c:scaling><c:orientation val="minMax"/>) <>
+                 ~s(<c:max val="0.5"/><c:min val="0"/></c:scaling><c:axPos val="r"/>)
+
+      assert chart =~ ~s(<c:numFmt formatCode="0%" sourceLinked="0"/>)
+      assert chart =~ ~r{<c:valAx><c:axId val="60010002"/>.*<a:t>Margin</a:t>.*<c:crosses val="max"/>}
+    end
+
+    test "writes log scale and reversed axes" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.insert_chart(5, 0, :column, [[values: "Data!$B$2:$B$4"]],
+          x_axis: [:reverse],
+          y_axis: [log_base: 10, minor_unit: 5]
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+      chart = part(content, "xl/charts/chart1.xml")
+
+      assert chart =~ ~s(<c:catAx><c:axId val="50010001"/><c:scaling><c:orientation val="maxMin"/>)
+      assert chart =~ ~s(<c:valAx><c:axId val="50010002"/><c:scaling><c:logBase val="10"/>)
+      assert chart =~ ~s(<c:minorUnit val="5"/>)
+    end
+
+    test "applies the x axis options to the horizontal value axis of a bar chart" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.insert_chart(5, 0, :bar, [[values: "Data!$B$2:$B$4"]], x_axis: [log_base: 10])
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert part(content, "xl/charts/chart1.xml") =~
+               ~s(<c:valAx><c:axId val="50010002"/><c:scaling><c:logBase val="10"/>)
+    end
+
+    test "returns an error when the combined chart has no values" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.insert_chart(5, 0, :column, [[values: "Data!$B$2:$B$4"]],
+          combine: {:line, [[name: "Missing"]]}
+        )
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+  end
+
+  describe "new_chartsheet/4" do
+    test "writes a chartsheet ahead of its data sheet" do
+      chartsheet =
+        XlsxWriter.new_chartsheet(
+          "Summary Chart",
+          :column,
+          [[name: "Revenue", categories: "Data!$A$2:$A$4", values: "Data!$B$2:$B$4"]],
+          title: "Revenue",
+          legend: :none
+        )
+        |> XlsxWriter.set_tab_color("#1F4E79")
+
+      assert {:ok, content} = XlsxWriter.generate([chartsheet, data_sheet("Data")])
+
+      assert part(content, "xl/workbook.xml") =~
+               ~s(<sheets><sheet name="Summary Chart" sheetId="1" r:id="rId1"/>) <>
+                 ~s(<sheet name="Data" sheetId="2" r:id="rId2"/></sheets>)
+
+      assert part(content, "xl/_rels/workbook.xml.rels") =~
+               ~s(<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/chartsheet" Target="chartsheets/sheet1.xml"/>)
+
+      assert part(content, "xl/chartsheets/sheet1.xml") =~
+               ~s(<sheetPr><tabColor rgb="FF1F4E79"/></sheetPr>)
+
+      # A chartsheet holds one full-page chart on an absolute anchor
+      assert part(content, "xl/drawings/drawing1.xml") =~ "<xdr:absoluteAnchor>"
+
+      assert part(content, "xl/drawings/_rels/drawing1.xml.rels") =~
+               ~s(relationships/chart" Target="../charts/chart1.xml"/>)
+
+      chart = part(content, "xl/charts/chart1.xml")
+      assert chart =~ "<a:t>Revenue</a:t>"
+      assert chart =~ "<c:f>Data!$B$2:$B$4</c:f>"
+      refute chart =~ "<c:legend>"
+    end
+
+    test "returns an error for an invalid chartsheet series" do
+      chartsheet = XlsxWriter.new_chartsheet("Summary Chart", :line, [[name: "No values"]])
+
+      assert {:error, _reason} = XlsxWriter.generate([chartsheet, data_sheet("Data")])
+    end
+  end
+end
@@ -0,0 +1,448 @@
+defmodule XlsxWriter.ConditionalFormatTest do
+  use ExUnit.Case, async: true
+
+  alias XlsxWriter
+
+  @highlight [{:bg_color, "#FFC7CE"}, {:font_color, "#9C0006"}]
+
+  defp data_sheet(name) do
+    [
+      {"Alpha", 120, ~D[2024-01-02]},
+      {"Beta", 45, ~D[2024-01-03]},
+      {"Alpha", 80, ~D[2024-01-04]},
+      {"Gamma", nil, ~D[2024-01-05]}
+    ]
+    |> Enum.with_index(1)
+    |> Enum.reduce(XlsxWriter.new_sheet(name), fn {{label, amount, date}, row}, sheet ->
+      sheet
+      |> XlsxWriter.write(row, 0, label)
+      |> XlsxWriter.write(row, 1, amount)
+      |> XlsxWriter.write(row, 2, date)
+    end)
+  end
+
+  describe "add_conditional_format/7" do
+    test "creates conditional format instruction" do
+      sheet =
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, {:cell, {:between, 50, 100}},
+          format: [:bold]
+        )
+
+      assert {"Test",
+              [
+                {:add_conditional_format, 1, 1, 4, 1, {:cell, {:between, 50, 100}},
+                 [format: [:bold]]}
+              ]} = sheet
+    end
+
+    test "writes cell value rules" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.write(0, 5, 100)
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, {:cell, {:greater_than, 100}},
+          format: @highlight
+        )
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, {:cell, {:not_between, 50, 90.5}},
+          format: [:italic]
+        )
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, {:cell, {:less_than_or_equal_to, "=$F$1"}},
+          format: [{:font_color, "#006100"}]
+        )
+        |> XlsxWriter.add_conditional_format(1, 0, 4, 0, {:cell, {:equal_to, "Alpha"}},
+          format: [:bold]
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      # Strings are quoted, "=" references are written as formulas
+      assert [
+               {"B2:B5",
+                [
+                  ~s(<cfRule type="cellIs" dxfId="0" priority="1" operator="greaterThan"><formula>100</formula>),
+                  ~s(<cfRule type="cellIs" dxfId="1" priority="2" operator="notBetween"><formula>50</formula><formula>90.5</formula>),
+                  ~s(<cfRule type="cellIs" dxfId="2" priority="3" operator="lessThanOrEqual"><formula>$F$1</formula>)
+                ]},
+               {"A2:A5",
+                [~s(<cfRule type="cellIs" dxfId="3" priority="4" operator="equal"><formula>"Alpha"</formula>)]}
+             ] = content |> sheet_xml() |> rules_by_range()
+
+      assert part(content, "xl/styles.xml") =~
+               ~s(<dxfs count="4">) <>
+                 ~s(<dxf><font><color rgb="FF9C0006"/></font><fill><patternFill><bgColor rgb="FFFFC7CE"/></patternFill></fill></dxf>) <>
+                 ~s(<dxf><font><i/></font></dxf>) <>
+                 ~s(<dxf><font><color rgb="FF006100"/></font></dxf>) <>
+                 ~s(<dxf><font><b/></font></dxf></dxfs>)
+    end
+
+    test "writes text, date, blank, error, duplicate and rank rules" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.add_conditional_format(1, 0, 4, 0, {:text, {:contains, "ph"}}, format: @highlight)
+        |> XlsxWriter.add_conditional_format(1, 0, 4, 0, {:text, {:begins_with, "G"}}, format: [:bold])
+        |> XlsxWriter.add_conditional_format(1, 0, 4, 0, {:text, {:ends_with, "a"}}, format: [:italic])
+        |> XlsxWriter.add_conditional_format(1, 0, 4, 0, {:text, {:does_not_contain, "x"}})
+        |> XlsxWriter.add_conditional_format(1, 2, 4, 2, {:date, :yesterday}, format: @highlight)
+        |> XlsxWriter.add_conditional_format(1, 2, 4, 2, {:date, :last_seven_days}, format: [:bold])
+        |> XlsxWriter.add_conditional_format(1, 2, 4, 2, {:date, :next_month}, format: [:italic])
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, :blanks, format: [{:bg_color, "#EEEEEE"}])
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, :no_errors, format: [:bold])
+        |> XlsxWriter.add_conditional_format(1, 0, 4, 0, :duplicate, format: @highlight)
+        |> XlsxWriter.add_conditional_format(1, 0, 4, 0, :unique, format: [:italic])
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, {:top, 2}, format: [:bold])
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, {:bottom_percent, 25}, format: @highlight)
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      # Rules with the same format share a dxfId
+      assert [
+               {"A2:A5",
+                [
+                  ~s{<cfRule type="containsText" dxfId="0" priority="1" operator="containsText" text="ph"><formula>NOT(ISERROR(SEARCH("ph",A2)))</formula>},
+                  ~s{<cfRule type="beginsWith" dxfId="1" priority="2" operator="beginsWith" text="G"><formula>LEFT(A2,1)="G"</formula>},
+                  ~s{<cfRule type="endsWith" dxfId="2" priority="3" operator="endsWith" text="a"><formula>RIGHT(A2,1)="a"</formula>},
+                  ~s{<cfRule type="notContainsText" dxfId="3" priority="4" operator="notContains" text="x"><formula>ISERROR(SEARCH("x",A2))</formula>},
+                  ~s(<cfRule type="duplicateValues" dxfId="0" priority="5"/>),
+                  ~s(<cfRule type="uniqueValues" dxfId="2" priority="6"/>)
+                ]},
+               {"C2:C5",
+                [
+                  ~s{<cfRule type="timePeriod" dxfId="0" priority="7" timePeriod="yesterday"><formula>FLOOR(C2,1)=TODAY()-1</formula>},
+                  ~s(<cfRule type="timePeriod" dxfId="1" priority="8" timePeriod="last7Days"><formula>) <> _,
+                  ~s(<cfRule type="timePeriod" dxfId="2" priority="9" timePeriod="nextMonth"><formula>) <> _
+                ]},
+               {"B2:B5",
+                [
+                  ~s{<cfRule type="containsBlanks" dxfId="4" priority="10"><formula>LEN(TRIM(B2))=0</formula>},
+                  ~s{<cfRule type="notContainsErrors" dxfId="1" priority="11"><formula>NOT(ISERROR(B2))</formula>},
+                  ~s(<cfRule type="top10" dxfId="1" priority="12" rank="2"/>),
+                  ~s(<cfRule type="top10" dxfId="0" priority="13" percent="1" bottom="1" rank="25"/>)
+                ]}
+             ] = content |> sheet_xml() |> rules_by_range()
+    end
+
+    test "raises on invalid format color" do
+      assert_raise XlsxWriter.Error, fn ->
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.add_conditional_format(0, 0, 5, 0, :duplicate, format: [{:bg_color, 123}])
+      end
+    end
+
+    test "returns an error for an out-of-range top rank" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, {:top, 0}, format: [:bold])
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+  end
+
+  describe "color scales, data bars and icon sets" do
+    test "writes two and three color scales" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.add_conditional_format(
+          1,
+          1,
+          4,
+          1,
+          {:two_color_scale, [min_color: "#FFFFFF", max_color: "#63BE7B"]}
+        )
+        |> XlsxWriter.add_conditional_format(
+          1,
+          1,
+          4,
+          1,
+          {:three_color_scale,
+           [
+             min: {:number, 0},
+             mid: {:percentile, 50},
+             max: {:formula, "=$B$5"},
+             min_color: "#F8696B",
+             mid_color: "#FFEB84",
+             max_color: "#63BE7B"
+           ]}
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert [
+               {"B2:B5",
+                [
+                  ~s(<cfRule type="colorScale" priority="1"><colorScale>) <>
+                    ~s(<cfvo type="min" val="0"/><cfvo type="max" val="0"/>) <>
+                    ~s(<color rgb="FFFFFFFF"/><color rgb="FF63BE7B"/></colorScale>),
+                  ~s(<cfRule type="colorScale" priority="2"><colorScale>) <>
+                    ~s(<cfvo type="num" val="0"/><cfvo type="percentile" val="50"/><cfvo type="formula" val="$B$5"/>) <>
+                    ~s(<color rgb="FFF8696B"/><color rgb="FFFFEB84"/><color rgb="FF63BE7B"/></colorScale>)
+                ]}
+             ] = content |> sheet_xml() |> rules_by_range()
+    end
+
+    test "returns an error for a midpoint on a two color scale" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.add_conditional_format(
+          1,
+          1,
+          4,
+          1,
+          {:two_color_scale, [mid: {:percent, 50}, mid_color: "#FFEB84"]}
+        )
+
+      assert {:error, reason} = XlsxWriter.generate([sheet])
+      assert reason =~ "Two color scales have no midpoint"
+    end
+
+    test "writes data bars with their x14 extensions" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, {:data_bar, []})
+        |> XlsxWriter.add_conditional_format(
+          1,
+          1,
+          4,
+          1,
+          {:data_bar,
+           [
+             :solid,
+             :no_border,
+             :bar_only,
+             min: {:percent, 10},
+             max: {:percent, 90},
+             fill_color: "#2E75B6",
+             negative_fill_color: "#C00000",
+             negative_border_color: "#C00000",
+             direction: :right_to_left,
+             axis_position: :midpoint,
+             axis_color: "#000000"
+           ]}
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+      xml = sheet_xml(content)
+
+      # :bar_only hides the value, the rest of the options live in the x14 rule
+      assert [
+               {"B2:B5",
+                [
+                  ~s(<cfRule type="dataBar" priority="1"><dataBar><cfvo type="min"/><cfvo type="max"/>) <>
+                    ~s(<color rgb="FF638EC6"/></dataBar>) <> _,
+                  ~s(<cfRule type="dataBar" priority="2"><dataBar showValue="0">) <>
+                    ~s(<cfvo type="percent" val="10"/><cfvo type="percent" val="90"/>) <>
+                    ~s(<color rgb="FF2E75B6"/></dataBar>) <> _
+                ]}
+             ] = rules_by_range(xml)
+
+      assert xml =~
+               ~s(<x14:dataBar minLength="0" maxLength="100" border="1" negativeBarBorderColorSameAsPositive="0">) <>
+                 ~s(<x14:cfvo type="autoMin"/><x14:cfvo type="autoMax"/><x14:borderColor rgb="FF638EC6"/>) <>
+                 ~s(<x14:negativeFillColor rgb="FFFF0000"/><x14:negativeBorderColor rgb="FFFF0000"/>) <>
+                 ~s(<x14:axisColor rgb="FF000000"/></x14:dataBar>)
+
+      assert xml =~
+               ~s(<x14:dataBar minLength="0" maxLength="100" gradient="0" direction="rightToLeft" axisPosition="middle">) <>
+                 ~s(<x14:cfvo type="percent"><xm:f>10</xm:f></x14:cfvo><x14:cfvo type="percent"><xm:f>90</xm:f></x14:cfvo>) <>
+                 ~s(<x14:negativeFillColor rgb="FFC00000"/><x14:axisColor rgb="FF000000"/></x14:dataBar>)
+
+      assert xml =~ ~s(<xm:sqref>B2:B5</xm:sqref>)
+    end
+
+    test "writes icon sets" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, {:icon_set, :three_traffic_lights, []})
+        |> XlsxWriter.add_conditional_format(
+          1,
+          1,
+          4,
+          1,
+          {:icon_set, :four_ratings, [:reverse, :icons_only]}
+        )
+        |> XlsxWriter.add_conditional_format(
+          1,
+          1,
+          4,
+          1,
+          {:icon_set, :five_arrows,
+           [
+             thresholds: [
+               :automatic,
+               {:number, 10},
+               {:percent, 40},
+               {:percentile, 60},
+               {:formula, "=$B$5"}
+             ]
+           ]}
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      # Three traffic lights is Excel's default icon set so it has no iconSet attribute,
+      # and an :automatic first threshold keeps the 0% start
+      assert [
+               {"B2:B5",
+                [
+                  ~s(<cfRule type="iconSet" priority="1"><iconSet>) <>
+                    ~s(<cfvo type="percent" val="0"/><cfvo type="percent" val="33"/><cfvo type="percent" val="67"/></iconSet>),
+                  ~s(<cfRule type="iconSet" priority="2"><iconSet iconSet="4Rating" showValue="0" reverse="1">) <>
+                    ~s(<cfvo type="percent" val="0"/><cfvo type="percent" val="25"/>) <>
+                    ~s(<cfvo type="percent" val="50"/><cfvo type="percent" val="75"/></iconSet>),
+                  ~s(<cfRule type="iconSet" priority="3"><iconSet iconSet="5Arrows">) <>
+                    ~s(<cfvo type="percent" val="0"/><cfvo type="num" val="10"/><cfvo type="percent" val="40"/>) <>
+                    ~s(<cfvo type="percentile" val="60"/><cfvo type="formula" val="$B$5"/></iconSet>)
+                ]}
+             ] = content |> sheet_xml() |> rules_by_range()
+    end
+
+    test "returns an error when icon thresholds do not match the icon count" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.add_conditional_format(
+          1,
+          1,
+          4,
+          1,
+          {:icon_set, :three_arrows, [thresholds: [{:percent, 0}, {:percent, 50}]]}
+        )
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+  end
+
+  describe "formula rules, ranges and priority" do
+    test "turns stop_if_true into a flag and drops it when false" do
+      sheet =
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.add_conditional_format(1, 0, 5, 0, {:formula, "=$A2>0"},
+          stop_if_true: true,
+          priority: 2
+        )
+        |> XlsxWriter.add_conditional_format(1, 0, 5, 0, {:formula, "=$A2<0"}, stop_if_true: false)
+
+      assert {"Test",
+              [
+                {:add_conditional_format, 1, 0, 5, 0, {:formula, "=$A2<0"}, []},
+                {:add_conditional_format, 1, 0, 5, 0, {:formula, "=$A2>0"},
+                 [:stop_if_true, priority: 2]}
+              ]} = sheet
+    end
+
+    test "writes formula rules over several ranges" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.add_conditional_format(1, 0, 4, 2, {:formula, "=$C2<TODAY()"},
+          format: @highlight,
+          ranges: ["E2:F5", "H2:H5"]
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert [
+               {"A2:C5 E2:F5 H2:H5",
+                [~s{<cfRule type="expression" dxfId="0" priority="1"><formula>$C2&lt;TODAY()</formula>}]}
+             ] = content |> sheet_xml() |> rules_by_range()
+    end
+
+    test "writes prioritized and stop-if-true rules" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, {:cell, {:greater_than, 100}},
+          format: [:bold],
+          priority: 3
+        )
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, {:data_bar, []})
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, {:formula, "=$B2>=1000"},
+          format: @highlight,
+          priority: 1,
+          stop_if_true: true
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      # The data bar has no priority so it goes after the highest requested one
+      assert [
+               {"B2:B5",
+                [
+                  ~s(<cfRule type="cellIs" dxfId="0" priority="3" operator="greaterThan"><formula>100</formula>),
+                  ~s(<cfRule type="dataBar" priority="4">) <> _,
+                  ~s(<cfRule type="expression" dxfId="1" priority="1" stopIfTrue="1"><formula>$B2&gt;=1000</formula>)
+                ]}
+             ] = content |> sheet_xml() |> rules_by_range()
+    end
+
+    test "writes the requested priorities across overlapping ranges" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.add_conditional_format(0, 0, 9, 3, :blanks, format: @highlight, priority: 1)
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, :errors, format: @highlight, priority: 2)
+        |> XlsxWriter.add_conditional_format(0, 0, 9, 3, :duplicate, format: @highlight, priority: 3)
+        |> XlsxWriter.add_conditional_format(0, 0, 9, 3, :unique, format: @highlight)
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert [{"A1:D10", ["1", "3", "4"]}, {"B2:B5", ["2"]}] =
+               content |> sheet_xml() |> priorities_by_range()
+    end
+
+    test "writes the requested priorities on x14 icon sets across ranges" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.add_conditional_format(0, 2, 9, 2, {:cell, {:greater_than, 5}}, format: @highlight, priority: 4)
+        |> XlsxWriter.add_conditional_format(0, 0, 9, 0, {:icon_set, :three_stars, []}, priority: 1)
+        |> XlsxWriter.add_conditional_format(0, 2, 9, 2, {:data_bar, []}, priority: 2)
+        |> XlsxWriter.add_conditional_format(0, 1, 9, 1, {:icon_set, :five_boxes, []})
+        |> XlsxWriter.add_conditional_format(0, 0, 9, 0, :blanks, format: @highlight, priority: 3)
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+      xml = sheet_xml(content)
+
+      assert [{"C1:C10", ["4", "2"]}, {"A1:A10", ["3"]}] = priorities_by_range(xml)
+
+      assert [{"A1:A10", ["1"]}, {"B1:B10", ["5"]}, {"C1:C10", []}] =
+               ~r{<x14:conditionalFormatting [^>]*>(.*?)<xm:sqref>([^<]+)</xm:sqref>}
+               |> Regex.scan(xml, capture: :all_but_first)
+               |> Enum.map(fn [rules, range] ->
+                 {range, Regex.scan(~r/priority="(\d+)"/, rules, capture: :all_but_first) |> List.flatten()}
+               end)
+    end
+
+    test "returns an error for a priority used twice on a sheet" do
+      sheet =
+        data_sheet("Data")
+        |> XlsxWriter.add_conditional_format(0, 0, 9, 3, :blanks, format: @highlight, priority: 1)
+        |> XlsxWriter.add_conditional_format(1, 1, 4, 1, :errors, format: @highlight, priority: 1)
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+  end
+
+  defp sheet_xml(content) do
+    {:ok, files} = :zip.unzip(content, [:memory])
+    {_name, xml} = List.keyfind(files, ~c"xl/worksheets/sheet1.xml", 0)
+    xml
+  end
+
+  defp part(content, path) do
+    {:ok, files} = :zip.unzip(content, [:memory])
+    {_name, xml} = List.keyfind(files, String.to_charlist(path), 0)
+    xml
+  end
+
+  # Each <cfRule> up to its closing tag, or whole when it's empty
+  defp rules_by_range(xml) do
+    ~r{<conditionalFormatting sqref="([^"]+)">(.*?)</conditionalFormatting>}
+    |> Regex.scan(xml, capture: :all_but_first)
+    |> Enum.map(fn [range, rules] ->
+      {range, Regex.scan(~r{<cfRule [^>]*/>|<cfRule .*?(?=</cfRule>)}, rules) |> List.flatten()}
+    end)
+  end
+
+  defp priorities_by_range(xml) do
+    ~r{<conditionalFormatting sqref="([^"]+)">(.*?)</conditionalFormatting>}
+    |> Regex.scan(xml, capture: :all_but_first)
+    |> Enum.map(fn [range, rules] ->
+      {range, Regex.scan(~r/priority="(\d+)"/, rules, capture: :all_but_first) |> List.flatten()}
+    end)
+  end
+end
@@ -0,0 +1,190 @@
+defmodule XlsxWriter.DataValidationTest do
+  use ExUnit.Case, async: true
+
+  alias XlsxWriter
+
+  describe "add_data_validation/7" do
+    test "creates data validation instruction" do
+      sheet =
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.add_data_validation(1, 0, 10, 0, {:list, ["Yes", "No"]}, ignore_blank: false)
+
+      assert {"Test",
+              [
+                {:add_data_validation, 1, 0, 10, 0, {:list, ["Yes", "No"]},
+                 [ignore_blank: false]}
+              ]} = sheet
+    end
+
+    test "writes an inline dropdown list and messages" do
+      sheet =
+        XlsxWriter.new_sheet("Requests")
+        |> XlsxWriter.write(0, 0, "Status")
+        |> XlsxWriter.add_data_validation(1, 0, 50, 0, {:list, ["Open", "In progress", "Closed"]},
+          ignore_blank: false,
+          input_title: "Status",
+          input_message: "Choose the current status",
+          error_title: "Invalid status",
+          error_message: "Pick a value from the list"
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert [
+               ~s(<dataValidation type="list" showInputMessage="1" showErrorMessage="1" ) <>
+                 ~s(errorTitle="Invalid status" error="Pick a value from the list" ) <>
+                 ~s(promptTitle="Status" prompt="Choose the current status" sqref="A2:A51">) <>
+                 ~s(<formula1>"Open,In progress,Closed"</formula1></dataValidation>)
+             ] = validations(content)
+    end
+
+    test "writes a dropdown from a range on a hidden sheet" do
+      lists =
+        XlsxWriter.new_sheet("Lists")
+        |> XlsxWriter.write(0, 0, "North")
+        |> XlsxWriter.write(1, 0, "South")
+        |> XlsxWriter.write(2, 0, "East")
+        |> XlsxWriter.hide_sheet()
+
+      sheet =
+        XlsxWriter.new_sheet("Orders")
+        |> XlsxWriter.write(0, 0, "Region")
+        |> XlsxWriter.add_data_validation(1, 0, 50, 0, {:list, "=Lists!$A$1:$A$3"})
+        |> XlsxWriter.add_data_validation(1, 1, 50, 1, {:list, "=$H$1:$H$3"}, hide_dropdown: true)
+
+      assert {:ok, content} = XlsxWriter.generate([sheet, lists])
+
+      # showDropDown="1" is how Excel spells a hidden in-cell dropdown
+      assert [
+               ~s(<dataValidation type="list" allowBlank="1" showInputMessage="1" showErrorMessage="1" sqref="A2:A51">) <>
+                 ~s(<formula1>Lists!$A$1:$A$3</formula1></dataValidation>),
+               ~s(<dataValidation type="list" allowBlank="1" showDropDown="1" showInputMessage="1" showErrorMessage="1" sqref="B2:B51">) <>
+                 ~s(<formula1>$H$1:$H$3</formula1></dataValidation>)
+             ] = validations(content)
+    end
+
+    test "returns an error when the inline list is too long" do
+      items = for i <- 1..100, do: "Option #{i}"
+
+      sheet =
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.add_data_validation(0, 0, 10, 0, {:list, items})
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+
+    test "returns an error when the input title is too long" do
+      sheet =
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.add_data_validation(0, 0, 10, 0, {:list, ["Yes", "No"]},
+          input_title: String.duplicate("x", 40)
+        )
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+  end
+
+  describe "value and formula rules" do
+    test "converts date and time bounds to ISO 8601 strings" do
+      sheet =
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.add_data_validation(0, 0, 10, 0, {:time, {:less_than, ~T[18:00:00]}})
+        |> XlsxWriter.add_data_validation(
+          0,
+          1,
+          10,
+          1,
+          {:date, {:not_between, ~D[2024-12-24], ~N[2024-12-26 00:00:00]}}
+        )
+
+      assert {"Test",
+              [
+                {:add_data_validation, 0, 1, 10, 1,
+                 {:date, {:not_between, "2024-12-24", "2024-12-26T00:00:00"}}, []},
+                {:add_data_validation, 0, 0, 10, 0, {:time, {:less_than, "18:00:00"}}, []}
+              ]} = sheet
+    end
+
+    test "writes number, date, time, text length and custom rules" do
+      sheet =
+        XlsxWriter.new_sheet("Template")
+        |> XlsxWriter.write(0, 7, 100)
+        |> XlsxWriter.add_data_validation(1, 0, 50, 0, {:whole_number, {:between, 1, 10}},
+          error_title: "Quantity",
+          error_message: "Enter a whole number from 1 to 10"
+        )
+        |> XlsxWriter.add_data_validation(
+          1,
+          1,
+          50,
+          1,
+          {:decimal, {:less_than_or_equal_to, "=$H$1"}},
+          error_style: :warning
+        )
+        |> XlsxWriter.add_data_validation(
+          1,
+          2,
+          50,
+          2,
+          {:date, {:greater_than_or_equal_to, ~D[2024-01-01]}},
+          error_style: :information
+        )
+        |> XlsxWriter.add_data_validation(
+          1,
+          3,
+          50,
+          3,
+          {:time, {:between, "09:00", ~T[17:30:00]}}
+        )
+        |> XlsxWriter.add_data_validation(1, 4, 50, 4, {:text_length, {:not_equal_to, 0}})
+        |> XlsxWriter.add_data_validation(1, 5, 50, 5, {:custom, "=ISNUMBER(F2)"},
+          error_style: :stop
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      # "between" and "stop" are the defaults so they have no attribute. Dates and
+      # times are written as serial numbers, "=" references without the "="
+      assert [
+               ~s(<dataValidation type="whole" allowBlank="1" showInputMessage="1" showErrorMessage="1" ) <>
+                 ~s(errorTitle="Quantity" error="Enter a whole number from 1 to 10" sqref="A2:A51">) <>
+                 ~s(<formula1>1</formula1><formula2>10</formula2></dataValidation>),
+               ~s(<dataValidation type="decimal" errorStyle="warning" operator="lessThanOrEqual" ) <>
+                 ~s(allowBlank="1" showInputMessage="1" showErrorMessage="1" sqref="B2:B51">) <>
+                 ~s(<formula1>$H$1</formula1></dataValidation>),
+               ~s(<dataValidation type="date" errorStyle="information" operator="greaterThanOrEqual" ) <>
+                 ~s(allowBlank="1" showInputMessage="1" showErrorMessage="1" sqref="C2:C51">) <>
+                 ~s(<formula1>45292</formula1></dataValidation>),
+               ~s(<dataValidation type="time" allowBlank="1" showInputMessage="1" showErrorMessage="1" sqref="D2:D51">) <>
+                 ~s(<formula1>0.375</formula1><formula2>0.7291666666666666</formula2></dataValidation>),
+               ~s(<dataValidation type="textLength" operator="notEqual" ) <>
+                 ~s(allowBlank="1" showInputMessage="1" showErrorMessage="1" sqref="E2:E51">) <>
+                 ~s(<formula1>0</formula1></dataValidation>),
+               ~s{<dataValidation type="custom" allowBlank="1" showInputMessage="1" showErrorMessage="1" sqref="F2:F51">} <>
+                 ~s{<formula1>ISNUMBER(F2)</formula1></dataValidation>}
+             ] = validations(content)
+    end
+
+    test "returns an error for a fractional whole number bound" do
+      sheet =
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.add_data_validation(0, 0, 10, 0, {:whole_number, {:greater_than, 1.5}})
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+
+    test "returns an error for an unparsable date bound" do
+      sheet =
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.add_data_validation(0, 0, 10, 0, {:date, {:less_than, "not a date"}})
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+  end
+
+  defp validations(content) do
+    {:ok, files} = :zip.unzip(content, [:memory])
+    {_name, xml} = List.keyfind(files, ~c"xl/worksheets/sheet1.xml", 0)
+    Regex.scan(~r{<dataValidation .*?</dataValidation>}, xml) |> List.flatten()
+  end
+end
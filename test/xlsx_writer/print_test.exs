@@ -0,0 +1,275 @@
+defmodule XlsxWriter.PrintTest do
+  use ExUnit.Case, async: true
+
+  alias XlsxWriter
+
+  defp sheet_xml(content), do: part(content, "xl/worksheets/sheet1.xml")
+
+  defp part(content, path) do
+    {:ok, files} = :zip.unzip(content, [:memory])
+    {_, xml} = List.keyfind(files, String.to_charlist(path), 0)
+    xml
+  end
+
+  defp report_sheet(name) do
+    Enum.reduce(0..40, XlsxWriter.new_sheet(name), fn row, sheet ->
+      sheet
+      |> XlsxWriter.write(row, 0, "Item #{row}")
+      |> XlsxWriter.write(row, 1, row * 10)
+    end)
+  end
+
+  describe "set_page_setup/2" do
+    test "normalizes fit_to_pages into a triple" do
+      sheet = XlsxWriter.new_sheet("Test") |> XlsxWriter.set_page_setup(fit_to_pages: {2, 3})
+
+      assert {"Test", [{:set_page_setup, [{:fit_to_pages, 2, 3}]}]} = sheet
+    end
+
+    test "writes a full page setup" do
+      sheet =
+        report_sheet("Report")
+        |> XlsxWriter.set_page_setup(
+          orientation: :landscape,
+          paper_size: 9,
+          margins: [left: 0.5, right: 0.5, top: 1.0, bottom: 1.0, header: 0.4, footer: 0.4],
+          center_horizontally: true,
+          center_vertically: true,
+          fit_to_pages: {1, 0},
+          page_order: :over_then_down,
+          first_page_number: 3
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+      xml = sheet_xml(content)
+
+      # A fit width of 1 is the default so only the unconstrained height is written
+      assert xml =~ ~s(<sheetPr><pageSetUpPr fitToPage="1"/></sheetPr>)
+
+      assert xml =~
+               ~s(<printOptions horizontalCentered="1" verticalCentered="1"/>) <>
+                 ~s(<pageMargins left="0.5" right="0.5" top="1" bottom="1" header="0.4" footer="0.4"/>) <>
+                 ~s(<pageSetup paperSize="9" fitToHeight="0" pageOrder="overThenDown" orientation="landscape" ) <>
+                 ~s(useFirstPageNumber="3" horizontalDpi="200" verticalDpi="200"/>)
+    end
+
+    test "writes a print scale and keeps the default margins that aren't set" do
+      sheet =
+        report_sheet("Report")
+        |> XlsxWriter.set_page_setup(orientation: :portrait, scale: 75, margins: [top: 0.5])
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+      xml = sheet_xml(content)
+
+      assert xml =~
+               ~s(<pageMargins left="0.7" right="0.7" top="0.5" bottom="0.75" header="0.3" footer="0.3"/>) <>
+                 ~s(<pageSetup scale="75" orientation="portrait" horizontalDpi="200" verticalDpi="200"/>)
+
+      refute xml =~ "<printOptions"
+      refute xml =~ "<pageSetUpPr"
+    end
+
+    test "returns an error for an out-of-range scale" do
+      sheet = report_sheet("Report") |> XlsxWriter.set_page_setup(scale: 500)
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+  end
+
+  describe "set_header_footer/2" do
+    test "turns header and footer images into path or binary instructions" do
+      logo = File.read!("bird.jpeg")
+
+      sheet =
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.set_header_footer(
+          header: "&L&G",
+          footer: "&R&G",
+          header_image: {:left, {:path, "bird.jpeg"}},
+          footer_image: {:right, logo}
+        )
+
+      assert {"Test",
+              [
+                {:set_header_footer,
+                 [
+                   {:header, "&L&G"},
+                   {:footer, "&R&G"},
+                   {:header_image_path, :left, "bird.jpeg"},
+                   {:footer_image, :right, ^logo}
+                 ]}
+              ]} = sheet
+    end
+
+    test "writes fields, fonts and images" do
+      sheet =
+        report_sheet("Report")
+        |> XlsxWriter.set_header_footer(
+          header: ~S(&L&G&C&"Arial,Bold"&14Quarterly Report&R&D &T),
+          footer: "&L&A&C&F&RPage &P of &N",
+          header_image: {:left, File.read!("bird.jpeg")},
+          scale_with_doc: false,
+          align_with_margins: false
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert sheet_xml(content) =~
+               ~s(<headerFooter scaleWithDoc="0" alignWithMargins="0">) <>
+                 ~s(<oddHeader>&amp;L&amp;G&amp;C&amp;"Arial,Bold"&amp;14Quarterly Report&amp;R&amp;D &amp;T</oddHeader>) <>
+                 ~s(<oddFooter>&amp;L&amp;A&amp;C&amp;F&amp;RPage &amp;P of &amp;N</oddFooter>) <>
+                 ~s(</headerFooter><legacyDrawingHF r:id="rId1"/>)
+
+      {:ok, files} = :zip.unzip(content, [:memory])
+      assert List.keymember?(files, ~c"xl/drawings/vmlDrawing1.vml", 0)
+    end
+
+    test "generates valid xlsx with first page and even page variants" do
+      sheet =
+        report_sheet("Report")
+        |> XlsxWriter.set_header_footer(
+          header: "&C&G",
+          footer: "&RPage &P",
+          first_header: "&CQuarterly Report",
+          first_footer: "&CConfidential",
+          even_header: "&LReport",
+          even_footer: "&LPage &P",
+          header_image: {:center, {:path, "bird.jpeg"}}
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      [header_footer] = Regex.run(~r/<headerFooter[^>]*>.*<\/headerFooter>/s, sheet_xml(content))
+      assert header_footer =~ ~s(differentOddEven="1")
+      assert header_footer =~ ~s(differentFirst="1")
+
+      assert Regex.scan(~r/<(\w+)>/, header_footer, capture: :all_but_first) ==
+               [
+                 ["oddHeader"],
+                 ["oddFooter"],
+                 ["evenHeader"],
+                 ["evenFooter"],
+                 ["firstHeader"],
+                 ["firstFooter"]
+               ]
+    end
+
+    test "writes first page and even page variants without a default header" do
+      sheet =
+        report_sheet("Report")
+        |> XlsxWriter.set_header_footer(first_header: "&CCover", even_footer: "&LPage &P")
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert sheet_xml(content) =~
+               ~s(<headerFooter differentOddEven="1" differentFirst="1">) <>
+                 ~s(<evenFooter>&amp;LPage &amp;P</evenFooter>) <>
+                 ~s(<firstHeader>&amp;CCover</firstHeader></headerFooter>)
+    end
+
+    test "keeps the header image drawing after the variants" do
+      sheet =
+        report_sheet("Report")
+        |> XlsxWriter.set_header_footer(
+          header: "&C&G",
+          first_header: "&CCover",
+          even_header: "&LReport",
+          header_image: {:center, {:path, "bird.jpeg"}}
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert sheet_xml(content) =~
+               ~s(<headerFooter differentOddEven="1" differentFirst="1">) <>
+                 ~s(<oddHeader>&amp;C&amp;G</oddHeader><evenHeader>&amp;LReport</evenHeader>) <>
+                 ~s(<firstHeader>&amp;CCover</firstHeader></headerFooter><legacyDrawingHF r:id="rId1"/>)
+
+      {:ok, files} = :zip.unzip(content, [:memory])
+      assert List.keymember?(files, ~c"xl/drawings/vmlDrawing1.vml", 0)
+    end
+
+    test "keeps an earlier align_with_margins setting" do
+      sheet =
+        report_sheet("Report")
+        |> XlsxWriter.set_header_footer(align_with_margins: false)
+        |> XlsxWriter.set_header_footer(header: "&CReport", first_header: "&CCover")
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+      assert sheet_xml(content) =~ ~s(alignWithMargins="0")
+    end
+
+    test "returns an error for an image without a &G placeholder" do
+      sheet =
+        report_sheet("Report")
+        |> XlsxWriter.set_header_footer(
+          header: "&CReport",
+          header_image: {:left, {:path, "bird.jpeg"}}
+        )
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+
+    test "returns an error for a header over 255 characters" do
+      sheet =
+        report_sheet("Report")
+        |> XlsxWriter.set_header_footer(first_header: String.duplicate("x", 256))
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+  end
+
+  describe "print areas, titles and page breaks" do
+    test "writes print area, repeat rows/columns and page breaks" do
+      sheet =
+        report_sheet("Ledger")
+        |> XlsxWriter.set_print_area(0, 0, 40, 1)
+        |> XlsxWriter.set_repeat_rows(0, 0)
+        |> XlsxWriter.set_repeat_columns(0, 0)
+        |> XlsxWriter.set_page_breaks([10, 20, 30])
+        |> XlsxWriter.set_vertical_page_breaks([1])
+        |> XlsxWriter.set_page_setup(
+          print_gridlines: true,
+          print_headings: true,
+          black_and_white: true,
+          draft: true
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert part(content, "xl/workbook.xml") =~
+               ~s(<definedNames>) <>
+                 ~s(<definedName name="_xlnm.Print_Area" localSheetId="0">Ledger!$A$1:$B$41</definedName>) <>
+                 ~s(<definedName name="_xlnm.Print_Titles" localSheetId="0">Ledger!$A:$A,Ledger!$1:$1</definedName>) <>
+                 ~s(</definedNames>)
+
+      xml = sheet_xml(content)
+      assert xml =~ ~s(<printOptions headings="1" gridLines="1"/>)
+
+      assert xml =~
+               ~s(<pageSetup orientation="portrait" blackAndWhite="1" draft="1" horizontalDpi="200" verticalDpi="200"/>) <>
+                 ~s(<rowBreaks count="3" manualBreakCount="3">) <>
+                 ~s(<brk id="10" max="16383" man="1"/><brk id="20" max="16383" man="1"/><brk id="30" max="16383" man="1"/>) <>
+                 ~s(</rowBreaks><colBreaks count="1" manualBreakCount="1"><brk id="1" max="1048575" man="1"/></colBreaks>)
+    end
+
+    test "returns an error for a page break before the first row or column" do
+      sheet = report_sheet("Ledger") |> XlsxWriter.set_page_breaks([0, 10])
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+
+      sheet = report_sheet("Ledger") |> XlsxWriter.set_vertical_page_breaks([0])
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+
+    test "returns an error for an inverted repeat row range" do
+      sheet = report_sheet("Ledger") |> XlsxWriter.set_repeat_rows(5, 2)
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+
+    test "returns an error for more than 1023 page breaks" do
+      sheet = report_sheet("Ledger") |> XlsxWriter.set_page_breaks(Enum.to_list(1..1024))
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+  end
+end
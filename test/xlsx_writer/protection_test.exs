@@ -0,0 +1,76 @@
+defmodule XlsxWriter.ProtectionTest do
+  use ExUnit.Case, async: true
+
+  alias XlsxWriter
+
+  describe "protect_sheet/2" do
+    test "creates protect instruction" do
+      sheet = XlsxWriter.new_sheet("Test") |> XlsxWriter.protect_sheet()
+
+      assert {"Test", [{:protect, []}]} = sheet
+    end
+
+    test "generates valid xlsx with a protected sheet and unlocked input cells" do
+      sheet =
+        XlsxWriter.new_sheet("Pricing")
+        |> XlsxWriter.write(0, 0, "Quantity")
+        |> XlsxWriter.write(0, 1, 10, format: [:unlocked, {:bg_color, "#FFF2CC"}])
+        |> XlsxWriter.write(1, 0, "Unit price")
+        |> XlsxWriter.write(1, 1, 2.5, format: [:locked])
+        |> XlsxWriter.write(2, 0, "Total")
+        |> XlsxWriter.write_formula(2, 1, "=B1*B2", format: [:hidden, :bold])
+        |> XlsxWriter.protect_sheet(password: "secret")
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      # "secret" hashes to DAA7 with Excel's legacy sheet password hash
+      assert part(content, "xl/worksheets/sheet1.xml") =~
+               ~s(<sheetProtection password="DAA7" sheet="1" objects="1" scenarios="1"/>)
+
+      # The explicitly locked cell keeps the default format, cells are locked
+      # unless their format says otherwise
+      styles = part(content, "xl/styles.xml")
+      assert styles =~ ~s(<cellXfs count="3">)
+      assert styles =~ ~s(applyFill="1" applyProtection="1"><protection locked="0"/></xf>)
+      assert styles =~ ~s(applyFont="1" applyProtection="1"><protection hidden="1"/></xf>)
+    end
+
+    test "generates valid xlsx with every allow flag" do
+      sheet =
+        XlsxWriter.new_sheet("Data")
+        |> XlsxWriter.write(0, 0, "Value")
+        |> XlsxWriter.protect_sheet(
+          select_locked_cells: false,
+          select_unlocked_cells: true,
+          format_cells: true,
+          format_columns: true,
+          format_rows: true,
+          insert_columns: true,
+          insert_rows: true,
+          insert_links: true,
+          delete_columns: true,
+          delete_rows: true,
+          sort: true,
+          autofilter: true,
+          pivot_tables: true,
+          edit_objects: true,
+          edit_scenarios: true
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      # Allowed actions are written as "0" (not protected), objects and
+      # scenarios are left out once editing them is allowed
+      assert part(content, "xl/worksheets/sheet1.xml") =~
+               ~s(<sheetProtection sheet="1" formatCells="0" formatColumns="0" formatRows="0" ) <>
+                 ~s(insertColumns="0" insertRows="0" insertHyperlinks="0" deleteColumns="0" deleteRows="0" ) <>
+                 ~s(selectLockedCells="1" sort="0" autoFilter="0" pivotTables="0"/>)
+    end
+  end
+
+  defp part(content, path) do
+    {:ok, files} = :zip.unzip(content, [:memory])
+    {_, xml} = List.keyfind(files, String.to_charlist(path), 0)
+    xml
+  end
+end
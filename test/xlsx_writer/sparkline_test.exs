@@ -0,0 +1,135 @@
+defmodule XlsxWriter.SparklineTest do
+  use ExUnit.Case, async: true
+
+  alias XlsxWriter
+
+  defp kpi_sheet(name) do
+    rows = [
+      ["North", 10, 12, -3, 15, 18],
+      ["South", 8, -2, 6, 9, 11],
+      ["East", 14, 13, 12, 10, 16]
+    ]
+
+    rows
+    |> Enum.with_index(1)
+    |> Enum.reduce(XlsxWriter.new_sheet(name), fn {values, row}, sheet ->
+      values
+      |> Enum.with_index()
+      |> Enum.reduce(sheet, fn {value, col}, sheet -> XlsxWriter.write(sheet, row, col, value) end)
+    end)
+  end
+
+  # The attributes and sparklines of each <x14:sparklineGroup>, by target cells
+  defp sparkline_groups(content) do
+    {:ok, files} = :zip.unzip(content, [:memory])
+    {_, xml} = List.keyfind(files, ~c"xl/worksheets/sheet1.xml", 0)
+
+    ~r{<x14:sparklineGroup ([^>]*)>(.*?)</x14:sparklineGroup>}
+    |> Regex.scan(xml, capture: :all_but_first)
+    |> Map.new(fn [attributes, body] ->
+      sparklines =
+        ~r{<xm:f>([^<]+)</xm:f><xm:sqref>([^<]+)</xm:sqref>}
+        |> Regex.scan(body, capture: :all_but_first)
+        |> Enum.map(&List.to_tuple/1)
+
+      {sparklines |> Enum.map(&elem(&1, 1)) |> Enum.join(" "), {attributes, body, sparklines}}
+    end)
+  end
+
+  describe "add_sparkline/5" do
+    test "creates sparkline instruction" do
+      sheet =
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.add_sparkline(0, 5, "Test!$A$1:$E$1")
+
+      assert {"Test", [{:add_sparkline, 0, 5, "Test!$A$1:$E$1", []}]} = sheet
+    end
+
+    test "writes line, column and win/loss sparklines" do
+      sheet =
+        kpi_sheet("KPIs")
+        |> XlsxWriter.add_sparkline(1, 6, "KPIs!$B$2:$F$2", [
+          :high_point,
+          :low_point,
+          :first_point,
+          :last_point,
+          :markers,
+          color: "#1F4E79",
+          high_point_color: "#00B050",
+          low_point_color: "#C00000",
+          line_weight: 1.5
+        ])
+        |> XlsxWriter.add_sparkline(2, 6, "KPIs!$B$3:$F$3", [
+          :negative_points,
+          :axis,
+          type: :column,
+          negative_points_color: "#FF0000",
+          min: -5,
+          max: 20
+        ])
+        |> XlsxWriter.add_sparkline(3, 6, "KPIs!$B$4:$F$4", type: :win_lose, style: 5)
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+      groups = sparkline_groups(content)
+
+      {line, body, sparklines} = groups["G2"]
+      assert line == ~s(lineWeight="1.5" displayEmptyCellsAs="gap" markers="1" high="1" low="1" first="1" last="1")
+      assert body =~ ~s(<x14:colorSeries rgb="FF1F4E79"/>)
+      assert body =~ ~s(<x14:colorHigh rgb="FF00B050"/><x14:colorLow rgb="FFC00000"/>)
+      assert sparklines == [{"KPIs!B2:F2", "G2"}]
+
+      {column, body, sparklines} = groups["G3"]
+
+      assert column ==
+               ~s(manualMax="20" manualMin="-5" type="column" displayEmptyCellsAs="gap" negative="1" ) <>
+                 ~s(displayXAxis="1" minAxisType="custom" maxAxisType="custom")
+
+      assert body =~ ~s(<x14:colorNegative rgb="FFFF0000"/>)
+      assert sparklines == [{"KPIs!B3:F3", "G3"}]
+
+      # Style 5 uses theme color 8
+      {win_lose, body, sparklines} = groups["G4"]
+      assert win_lose == ~s(type="stacked" displayEmptyCellsAs="gap")
+      assert body =~ ~s(<x14:colorSeries theme="8" tint="-0.499984740745262"/>)
+      assert sparklines == [{"KPIs!B4:F4", "G4"}]
+    end
+
+    test "raises on negative cell position" do
+      assert_raise ArgumentError, fn ->
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.add_sparkline(-1, 0, "Test!$A$1:$E$1")
+      end
+    end
+  end
+
+  describe "add_sparkline_group/7" do
+    test "writes a grouped sparkline" do
+      sheet =
+        kpi_sheet("KPIs")
+        |> XlsxWriter.add_sparkline_group(1, 6, 3, 6, "KPIs!$B$2:$F$4", [
+          :group_min,
+          :group_max,
+          :last_point,
+          type: :column
+        ])
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      # One group, with a sparkline per row of the data range
+      assert %{"G2 G3 G4" => {attributes, _body, sparklines}} = sparkline_groups(content)
+
+      assert attributes ==
+               ~s(type="column" displayEmptyCellsAs="gap" last="1" minAxisType="group" maxAxisType="group")
+
+      assert sparklines == [{"KPIs!B2:F2", "G2"}, {"KPIs!B3:F3", "G3"}, {"KPIs!B4:F4", "G4"}]
+    end
+
+    test "returns an error when the data range does not match the target cells" do
+      sheet =
+        kpi_sheet("KPIs")
+        |> XlsxWriter.add_sparkline_group(1, 6, 3, 6, "KPIs!$B$2:$F$3")
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+  end
+end
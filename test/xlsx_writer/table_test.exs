@@ -0,0 +1,274 @@
+defmodule XlsxWriter.TableTest do
+  use ExUnit.Case, async: true
+
+  alias XlsxWriter
+
+  defp part(content, path) do
+    {:ok, files} = :zip.unzip(content, [:memory])
+    {_, xml} = List.keyfind(files, String.to_charlist(path), 0)
+    xml
+  end
+
+  defp sales_sheet(name) do
+    [
+      {"North", "Widgets", 120},
+      {"South", "Gadgets", 45},
+      {"East", "Widgets", 80}
+    ]
+    |> Enum.with_index(1)
+    |> Enum.reduce(XlsxWriter.new_sheet(name), fn {{region, product, amount}, row}, sheet ->
+      sheet
+      |> XlsxWriter.write(row, 0, region)
+      |> XlsxWriter.write(row, 1, product)
+      |> XlsxWriter.write(row, 2, amount)
+    end)
+  end
+
+  describe "add_table/6" do
+    test "normalizes column header names" do
+      sheet =
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.add_table(0, 0, 3, 1, columns: ["Region", [header: "Amount"]])
+
+      assert {"Test",
+              [{:add_table, 0, 0, 3, 1, [columns: [[header: "Region"], [header: "Amount"]]]}]} =
+               sheet
+    end
+
+    test "writes a default table" do
+      sheet = sales_sheet("Sales") |> XlsxWriter.add_table(0, 0, 3, 2)
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert part(content, "xl/tables/table1.xml") =~
+               ~s(id="1" name="Table1" displayName="Table1" ref="A1:C4" totalsRowShown="0">) <>
+                 ~s(<autoFilter ref="A1:C4"/><tableColumns count="3">) <>
+                 ~s(<tableColumn id="1" name="Column1"/><tableColumn id="2" name="Column2"/><tableColumn id="3" name="Column3"/>) <>
+                 ~s(</tableColumns><tableStyleInfo name="TableStyleMedium9" ) <>
+                 ~s(showFirstColumn="0" showLastColumn="0" showRowStripes="1" showColumnStripes="0"/></table>)
+
+      assert part(content, "xl/worksheets/sheet1.xml") =~
+               ~s(<tableParts count="1"><tablePart r:id="rId1"/></tableParts>)
+    end
+
+    test "writes a named, styled table and one without a header row" do
+      sheet =
+        sales_sheet("Sales")
+        |> XlsxWriter.add_table(0, 0, 3, 2,
+          name: "Sales",
+          style: {:dark, 11},
+          banded_rows: false,
+          banded_columns: true,
+          first_column: true,
+          last_column: true,
+          columns: ["Region", "Product", "Amount"]
+        )
+        |> XlsxWriter.add_table(5, 0, 8, 1,
+          style: :none,
+          header_row: false,
+          autofilter: false
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert part(content, "xl/tables/table1.xml") =~
+               ~s(id="1" name="Sales" displayName="Sales" ref="A1:C4" totalsRowShown="0">) <>
+                 ~s(<autoFilter ref="A1:C4"/><tableColumns count="3">) <>
+                 ~s(<tableColumn id="1" name="Region"/><tableColumn id="2" name="Product"/><tableColumn id="3" name="Amount"/>) <>
+                 ~s(</tableColumns><tableStyleInfo name="TableStyleDark11" ) <>
+                 ~s(showFirstColumn="1" showLastColumn="1" showRowStripes="0" showColumnStripes="1"/></table>)
+
+      # :none drops the style name, and a table without a header row has no autofilter
+      table2 = part(content, "xl/tables/table2.xml")
+
+      assert table2 =~
+               ~s(id="2" name="Table2" displayName="Table2" ref="A6:B9" headerRowCount="0" totalsRowShown="0">) <>
+                 ~s(<tableColumns count="2">)
+
+      assert table2 =~
+               ~s(<tableStyleInfo showFirstColumn="0" showLastColumn="0" showRowStripes="1" showColumnStripes="0"/>)
+
+      refute table2 =~ "<autoFilter"
+
+      assert part(content, "xl/worksheets/sheet1.xml") =~
+               ~s(<tableParts count="2"><tablePart r:id="rId1"/><tablePart r:id="rId2"/></tableParts>)
+    end
+
+    test "returns an error for an out-of-range style number" do
+      sheet = sales_sheet("Sales") |> XlsxWriter.add_table(0, 0, 3, 2, style: {:light, 22})
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+
+    test "returns an error for overlapping tables" do
+      sheet =
+        sales_sheet("Sales")
+        |> XlsxWriter.add_table(0, 0, 3, 2)
+        |> XlsxWriter.add_table(2, 1, 5, 3)
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+  end
+
+  describe "total rows and calculated columns" do
+    test "writes a total row and a calculated column" do
+      sheet =
+        XlsxWriter.new_sheet("Invoice")
+        |> XlsxWriter.write(1, 0, "Widget")
+        |> XlsxWriter.write(1, 1, 3)
+        |> XlsxWriter.write(1, 2, 9.99)
+        |> XlsxWriter.write(2, 0, "Gadget")
+        |> XlsxWriter.write(2, 1, 1)
+        |> XlsxWriter.write(2, 2, 24.5)
+        |> XlsxWriter.add_table(0, 0, 3, 3,
+          name: "Invoice",
+          total_row: true,
+          columns: [
+            [header: "Item", total_label: "Total"],
+            [header: "Qty", total_function: :sum],
+            [header: "Price", total_function: :average, format: [{:num_format, "0.00"}]],
+            [
+              header: "Amount",
+              header_format: [:bold, {:align, :right}],
+              formula: "=[@Qty]*[@Price]",
+              total_function: :sum,
+              format: [{:num_format, "#,##0.00"}]
+            ]
+          ]
+        )
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      # The autofilter stops above the total row, structured references are
+      # written in their long "#This Row" form
+      assert part(content, "xl/tables/table1.xml") =~
+               ~s(id="1" name="Invoice" displayName="Invoice" ref="A1:D4" totalsRowCount="1">) <>
+                 ~s(<autoFilter ref="A1:D3"/><tableColumns count="4">) <>
+                 ~s(<tableColumn id="1" name="Item" totalsRowLabel="Total"/>) <>
+                 ~s(<tableColumn id="2" name="Qty" totalsRowFunction="sum"/>) <>
+                 ~s(<tableColumn id="3" name="Price" totalsRowFunction="average" dataDxfId="0"/>) <>
+                 ~s(<tableColumn id="4" name="Amount" totalsRowFunction="sum" dataDxfId="1">) <>
+                 ~s(<calculatedColumnFormula>[[#This Row],Qty]*[[#This Row],Price]</calculatedColumnFormula></tableColumn>)
+
+      sheet_xml = part(content, "xl/worksheets/sheet1.xml")
+      assert sheet_xml =~ ~s{<c r="D2" s="3"><f>[[#This Row],Qty]*[[#This Row],Price]</f>}
+      assert sheet_xml =~ ~s{<c r="B4"><f>SUBTOTAL(109,[Qty])</f>}
+      assert sheet_xml =~ ~s{<c r="C4" s="1"><f>SUBTOTAL(101,[Price])</f>}
+      assert sheet_xml =~ ~s{<c r="D4" s="3"><f>SUBTOTAL(109,[Amount])</f>}
+    end
+
+    test "writes every total function" do
+      functions = [:count, :count_numbers, :max, :min, :std_dev, :var, {:custom, "=SUM([Qty])/2"}]
+
+      columns =
+        functions
+        |> Enum.with_index(1)
+        |> Enum.map(fn {function, i} -> [header: "Col#{i}", total_function: function] end)
+
+      sheet =
+        XlsxWriter.new_sheet("Totals")
+        |> XlsxWriter.add_table(0, 0, 3, length(columns), total_row: true, columns: [[header: "Qty"] | columns])
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert part(content, "xl/tables/table1.xml") =~
+               ~s(<tableColumn id="1" name="Qty"/>) <>
+                 ~s(<tableColumn id="2" name="Col1" totalsRowFunction="count"/>) <>
+                 ~s(<tableColumn id="3" name="Col2" totalsRowFunction="countNums"/>) <>
+                 ~s(<tableColumn id="4" name="Col3" totalsRowFunction="max"/>) <>
+                 ~s(<tableColumn id="5" name="Col4" totalsRowFunction="min"/>) <>
+                 ~s(<tableColumn id="6" name="Col5" totalsRowFunction="stdDev"/>) <>
+                 ~s(<tableColumn id="7" name="Col6" totalsRowFunction="var"/>) <>
+                 ~s(<tableColumn id="8" name="Col7" totalsRowFunction="custom">) <>
+                 ~s{<totalsRowFormula>SUM([Qty])/2</totalsRowFormula></tableColumn>}
+
+      assert part(content, "xl/worksheets/sheet1.xml") =~
+               ~s{<c r="B4"><f>SUBTOTAL(103,[Col1])</f><v>0</v></c>} <>
+                 ~s{<c r="C4"><f>SUBTOTAL(102,[Col2])</f><v>0</v></c>} <>
+                 ~s{<c r="D4"><f>SUBTOTAL(104,[Col3])</f><v>0</v></c>} <>
+                 ~s{<c r="E4"><f>SUBTOTAL(105,[Col4])</f><v>0</v></c>} <>
+                 ~s{<c r="F4"><f>SUBTOTAL(107,[Col5])</f><v>0</v></c>} <>
+                 ~s{<c r="G4"><f>SUBTOTAL(110,[Col6])</f><v>0</v></c>} <>
+                 ~s{<c r="H4"><f>SUM([Qty])/2</f><v>0</v></c>}
+    end
+
+    test "raises on invalid column format color" do
+      assert_raise XlsxWriter.Error, fn ->
+        XlsxWriter.new_sheet("Test")
+        |> XlsxWriter.add_table(0, 0, 3, 0, columns: [[header: "Qty", format: [{:bg_color, 1}]]])
+      end
+    end
+  end
+
+  describe "write_table/5" do
+    test "writes the rows below the header and sizes the table to fit" do
+      sheet =
+        XlsxWriter.new_sheet("Sales")
+        |> XlsxWriter.write_table(2, 1, [["North", 120], ["South", 45], ["East", 80]],
+          name: "Sales",
+          total_row: true,
+          columns: [[header: "Region", total_label: "Total"], [header: "Amount", total_function: :sum]]
+        )
+
+      assert {"Sales",
+              [
+                {:add_table, 2, 1, 6, 2, [name: "Sales", total_row: true, columns: _]},
+                {:write, 5, 2, {:float, 80}} | _
+              ]} = sheet
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+      assert part(content, "xl/tables/table1.xml") =~ ~s(ref="B3:C7")
+
+      sheet_xml = part(content, "xl/worksheets/sheet1.xml")
+      assert sheet_xml =~ ~s(<c r="C4"><v>120</v></c>)
+      assert sheet_xml =~ ~s(<c r="C6"><v>80</v></c>)
+      refute sheet_xml =~ ~s(r="D)
+
+      shared_strings = part(content, "xl/sharedStrings.xml")
+      for text <- ["Region", "Amount", "North", "South", "East"], do: assert(shared_strings =~ text)
+    end
+
+    test "writes nil values as blank cells" do
+      sheet =
+        XlsxWriter.new_sheet("Sales")
+        |> XlsxWriter.write_table(0, 0, [["North", nil]], columns: ["Region", "Amount"])
+
+      assert {"Sales", [{:add_table, 0, 0, 1, 1, _}, {:write, 1, 1, {:blank, []}} | _]} = sheet
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+      refute part(content, "xl/worksheets/sheet1.xml") =~ ~s(<c r="B2")
+    end
+
+    test "raises for a row with more values than columns" do
+      assert_raise ArgumentError, fn ->
+        XlsxWriter.new_sheet("Sales")
+        |> XlsxWriter.write_table(0, 0, [["North", 120, "extra"]], columns: ["Region", "Amount"])
+      end
+    end
+
+    test "starts the rows at the first row without a header row" do
+      sheet =
+        XlsxWriter.new_sheet("Sales")
+        |> XlsxWriter.write_table(0, 0, [["North", 120]], header_row: false, columns: ["Region", "Amount"])
+
+      assert {"Sales", [{:add_table, 0, 0, 0, 1, _}, {:write, 0, 1, {:float, 120}} | _]} = sheet
+    end
+
+    test "keeps an empty data row for a table without rows" do
+      sheet = XlsxWriter.new_sheet("Sales") |> XlsxWriter.write_table(0, 0, [], columns: ["Region"])
+
+      assert {"Sales", [{:add_table, 0, 0, 1, 0, _}]} = sheet
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert part(content, "xl/tables/table1.xml") =~
+               ~s(ref="A1:A2" totalsRowShown="0"><autoFilter ref="A1:A2"/>) <>
+                 ~s(<tableColumns count="1"><tableColumn id="1" name="Region"/></tableColumns>)
+    end
+
+    test "raises without columns" do
+      assert_raise ArgumentError, fn ->
+        XlsxWriter.new_sheet("Sales") |> XlsxWriter.write_table(0, 0, [["North", 120]])
+      end
+    end
+  end
+end
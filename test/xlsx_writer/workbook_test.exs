@@ -0,0 +1,187 @@
+defmodule XlsxWriter.WorkbookTest do
+  use ExUnit.Case, async: true
+
+  alias XlsxWriter.Workbook
+
+  doctest XlsxWriter.Workbook
+
+  describe "incremental workbook" do
+    test "applies batches streamed into several sheets" do
+      workbook = Workbook.new()
+      :ok = Workbook.add_sheet(workbook, "Ledger")
+      :ok = Workbook.add_sheet(workbook, "Summary")
+
+      1..5_000
+      |> Stream.chunk_every(1_000)
+      |> Enum.each(fn rows ->
+        batch =
+          Enum.reduce(rows, XlsxWriter.new_sheet("Ledger"), fn row, sheet ->
+            sheet
+            |> XlsxWriter.write(row, 0, "Entry #{row}")
+            |> XlsxWriter.write(row, 1, row * 1.5, format: [{:num_format, "0.00"}])
+          end)
+
+        assert :ok = Workbook.apply_instructions(workbook, batch)
+      end)
+
+      header =
+        XlsxWriter.new_sheet("Ledger")
+        |> XlsxWriter.write(0, 0, "Description", format: [:bold])
+        |> XlsxWriter.write(0, 1, "Amount", format: [:bold])
+        |> XlsxWriter.freeze_panes(1, 0)
+        |> XlsxWriter.group_rows(1, 1_000)
+
+      assert :ok = Workbook.apply_instructions(workbook, header)
+
+      summary = XlsxWriter.new_sheet("Summary") |> XlsxWriter.write_formula(0, 0, "=SUM(Ledger!B:B)")
+      assert :ok = Workbook.apply_instructions(workbook, summary)
+
+      assert {:ok, content} =
+               Workbook.finish(workbook,
+                 properties: %XlsxWriter.WorkbookProperties{title: "Ledger"},
+                 defined_names: [{"Amounts", "=Ledger!$B$2:$B$5001"}]
+               )
+
+      assert <<80, 75, _::binary>> = content
+    end
+
+    test "returns an error for a sheet that was not added" do
+      workbook = Workbook.new()
+      :ok = Workbook.add_sheet(workbook, "Ledger")
+
+      batch = XlsxWriter.new_sheet("Other") |> XlsxWriter.write(0, 0, "Hello")
+
+      assert {:error, _reason} = Workbook.apply_instructions(workbook, batch)
+    end
+
+    test "returns an error for a failing instruction and keeps the workbook usable" do
+      workbook = Workbook.new()
+      :ok = Workbook.add_sheet(workbook, "Ledger")
+
+      bad = XlsxWriter.new_sheet("Ledger") |> XlsxWriter.set_repeat_rows(5, 2)
+      assert {:error, _reason} = Workbook.apply_instructions(workbook, bad)
+
+      good = XlsxWriter.new_sheet("Ledger") |> XlsxWriter.write(0, 0, "Hello")
+      assert :ok = Workbook.apply_instructions(workbook, good)
+      assert {:ok, _content} = Workbook.finish(workbook)
+    end
+
+    test "returns an error for chartsheets" do
+      workbook = Workbook.new()
+      :ok = Workbook.add_sheet(workbook, "Chart")
+
+      chartsheet = XlsxWriter.new_chartsheet("Chart", :column, [[values: "Data!$A$1:$A$3"]])
+
+      assert {:error, _reason} = Workbook.apply_instructions(workbook, chartsheet)
+    end
+
+    test "returns an error when used after finishing" do
+      workbook = Workbook.new()
+      :ok = Workbook.add_sheet(workbook, "Ledger")
+      {:ok, _content} = Workbook.finish(workbook)
+
+      assert {:error, _reason} = Workbook.add_sheet(workbook, "More")
+
+      assert {:error, _reason} =
+               Workbook.apply_instructions(workbook, XlsxWriter.new_sheet("Ledger"))
+    end
+
+    test "encrypts the finished workbook with a password" do
+      workbook = Workbook.new()
+      :ok = Workbook.add_sheet(workbook, "Ledger")
+
+      assert {:ok, <<0xD0, 0xCF, 0x11, 0xE0, _::binary>>} =
+               Workbook.finish(workbook, password: "secret")
+    end
+
+    test "streams batches into a low memory workbook" do
+      workbook = Workbook.new(memory: :low)
+      :ok = Workbook.add_sheet(workbook, "Ledger")
+      :ok = Workbook.add_sheet(workbook, "Summary", memory: :standard)
+
+      for batch <- 0..2 do
+        sheet =
+          Enum.reduce(0..99, XlsxWriter.new_sheet("Ledger"), fn row, sheet ->
+            XlsxWriter.write(sheet, batch * 100 + row, 0, "row #{row}")
+          end)
+
+        assert :ok = Workbook.apply_instructions(workbook, sheet)
+      end
+
+      assert {:ok, <<80, 75, _::binary>>} = Workbook.finish(workbook)
+    end
+
+    test "applies batches referencing workbook styles" do
+      workbook = Workbook.new(styles: [{"header", [:bold]}])
+      :ok = Workbook.add_sheet(workbook, "Ledger")
+
+      batch = XlsxWriter.new_sheet("Ledger") |> XlsxWriter.write(0, 0, "Date", style: "header")
+      assert :ok = Workbook.apply_instructions(workbook, batch)
+
+      missing = XlsxWriter.new_sheet("Ledger") |> XlsxWriter.write(1, 0, "Total", style: "total")
+      assert {:error, _reason} = Workbook.apply_instructions(workbook, missing)
+
+      assert {:ok, <<80, 75, _::binary>>} = Workbook.finish(workbook)
+    end
+
+    test "returns an error for memory settings after the workbook is created" do
+      workbook = Workbook.new()
+      :ok = Workbook.add_sheet(workbook, "Ledger")
+
+      batch = XlsxWriter.new_sheet("Ledger", memory: :constant) |> XlsxWriter.write(0, 0, "Hello")
+      assert {:error, _reason} = Workbook.apply_instructions(workbook, batch)
+      assert {:ok, <<80, 75, _::binary>>} = Workbook.finish(workbook)
+    end
+
+    test "returns an error for :memory and :tempdir when finishing" do
+      workbook = Workbook.new(memory: :constant)
+      :ok = Workbook.add_sheet(workbook, "Ledger")
+      :ok = Workbook.apply_instructions(workbook, XlsxWriter.new_sheet("Ledger") |> XlsxWriter.write(0, 0, 1))
+
+      assert {:error, "The :memory and :tempdir options are set when the workbook is created"} =
+               Workbook.finish(workbook, memory: :low)
+
+      assert {:error, "The :memory and :tempdir options are set when the workbook is created"} =
+               Workbook.finish(workbook, tempdir: System.tmp_dir!())
+
+      # Neither attempt finished the workbook
+      assert {:ok, <<80, 75, _::binary>>} = Workbook.finish(workbook)
+    end
+
+    test "applies the save-time instructions of a constant memory workbook" do
+      workbook = Workbook.new(memory: :constant)
+      :ok = Workbook.add_sheet(workbook, "Ledger")
+
+      rows = Enum.reduce(0..9, XlsxWriter.new_sheet("Ledger"), &XlsxWriter.write(&2, &1, 0, &1))
+      :ok = Workbook.apply_instructions(workbook, rows)
+
+      batch =
+        XlsxWriter.new_sheet("Ledger")
+        |> XlsxWriter.group_columns(1, 2)
+        |> XlsxWriter.add_conditional_format(0, 0, 9, 0, :blanks, format: [:bold], priority: 2)
+        |> XlsxWriter.add_conditional_format(0, 0, 9, 0, :errors, format: [:bold], priority: 1)
+        |> XlsxWriter.set_header_footer(first_header: "&CCover")
+
+      :ok = Workbook.apply_instructions(workbook, batch)
+
+      row_groups = XlsxWriter.new_sheet("Ledger") |> XlsxWriter.group_rows(1, 3)
+      assert {:error, _reason} = Workbook.apply_instructions(workbook, row_groups)
+
+      assert {:ok, content} = Workbook.finish(workbook)
+      {:ok, files} = :zip.unzip(content, [:memory])
+      {_, xml} = List.keyfind(files, ~c"xl/worksheets/sheet1.xml", 0)
+
+      assert xml =~ ~s(<cols><col min="2" max="3" width="9.140625" outlineLevel="1"/></cols>)
+      assert xml =~ ~s(<cfRule type="containsBlanks" dxfId="0" priority="2">)
+      assert xml =~ ~s(<cfRule type="containsErrors" dxfId="0" priority="1">)
+      assert xml =~ ~s(<headerFooter differentFirst="1"><firstHeader>&amp;CCover</firstHeader></headerFooter>)
+      refute xml =~ "outlineLevelRow"
+    end
+
+    test "raises for an invalid tempdir" do
+      assert_raise ArgumentError, fn ->
+        Workbook.new(memory: :constant, tempdir: "/nonexistent/xlsx_writer")
+      end
+    end
+  end
+end
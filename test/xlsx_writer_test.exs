@@ -96,6 +96,109 @@ defmodule XlsxWriterTest do
     end
   end
 
+  describe "shared formats" do
+    test "generates valid xlsx when cells of different types share a format list" do
+      sheet =
+        Enum.reduce(0..49, XlsxWriter.new_sheet("Shared"), fn row, sheet ->
+          sheet
+          |> XlsxWriter.write(row, 0, "Item #{row}", format: [:bold, {:align, :right}])
+          |> XlsxWriter.write(row, 1, row * 2.5, format: [:bold, {:align, :right}])
+          |> XlsxWriter.write(row, 2, ~D[2024-01-15], format: [:bold, {:align, :right}])
+          |> XlsxWriter.write(row, 3, ~D[2024-01-15])
+        end)
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      {:ok, files} = :zip.unzip(content, [:memory])
+      {_, styles} = List.keyfind(files, ~c"xl/styles.xml", 0)
+      {_, sheet_xml} = List.keyfind(files, ~c"xl/worksheets/sheet1.xml", 0)
+
+      # Default, shared format, shared format with a date, plain date
+      assert styles =~ ~s(<cellXfs count="4">)
+      assert sheet_xml =~ ~s(<c r="A50" s="1" t="s">)
+      assert sheet_xml =~ ~s(<c r="B50" s="1">)
+      assert sheet_xml =~ ~s(<c r="C50" s="2">)
+      assert sheet_xml =~ ~s(<c r="D50" s="3">)
+    end
+  end
+
+  describe "named styles" do
+    @styles [
+      {"header", [:bold, {:bg_color, "#4472C4"}]},
+      {"currency", [{:num_format, "$#,##0.00"}]}
+    ]
+
+    test "generates valid xlsx with cells referencing styles by name and index" do
+      sheet =
+        XlsxWriter.new_sheet("Invoices")
+        |> XlsxWriter.write(0, 0, "Amount", style: "header")
+        |> XlsxWriter.write(1, 0, 1250.5, style: "currency")
+        |> XlsxWriter.write(2, 0, 980, style: 1)
+        |> XlsxWriter.write(3, 0, ~D[2024-01-15], style: "header")
+        |> XlsxWriter.write(4, 0, true, style: "header")
+        |> XlsxWriter.write_formula(5, 0, "=SUM(A2:A3)", style: "currency")
+        |> XlsxWriter.write_boolean(6, 0, false, style: 0)
+        |> XlsxWriter.write_blank(7, 0, style: "header")
+
+      assert {:ok, content} = XlsxWriter.generate([sheet], styles: @styles)
+      assert <<80, _>> <> _ = content
+    end
+
+    test "returns an error for an undefined style" do
+      sheet = XlsxWriter.new_sheet("Invoices") |> XlsxWriter.write(0, 0, 1, style: "missing")
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet], styles: @styles)
+    end
+
+    test "returns an error for an out-of-range style index" do
+      sheet = XlsxWriter.new_sheet("Invoices") |> XlsxWriter.write(0, 0, 1, style: 2)
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet], styles: @styles)
+    end
+
+    test "returns an error for a duplicate style name" do
+      sheet = XlsxWriter.new_sheet("Invoices")
+
+      assert {:error, _reason} =
+               XlsxWriter.generate([sheet], styles: [{"header", [:bold]}, {"header", []}])
+    end
+
+    test "applies the style to every cell of a merged range" do
+      sheet =
+        XlsxWriter.new_sheet("Invoices")
+        |> XlsxWriter.merge_range(0, 0, 0, 2, "Report", style: "header")
+        |> XlsxWriter.merge_range(1, 0, 1, 2, 1250.5, style: "currency")
+
+      assert {:ok, content} = XlsxWriter.generate([sheet], styles: @styles)
+
+      {:ok, files} = :zip.unzip(content, [:memory])
+      {_, xml} = List.keyfind(files, ~c"xl/worksheets/sheet1.xml", 0)
+
+      for cell <- ["A1", "B1", "C1"], do: assert(xml =~ ~s(<c r="#{cell}" s="1"))
+      for cell <- ["A2", "B2", "C2"], do: assert(xml =~ ~s(<c r="#{cell}" s="2"))
+      assert xml =~ ~s(<mergeCell ref="A1:C1"/>)
+    end
+
+    test "raises when a style is given for a URL or rich string" do
+      sheet = XlsxWriter.new_sheet("Invoices")
+
+      assert_raise ArgumentError, fn ->
+        XlsxWriter.write_url(sheet, 0, 0, "https://example.com", style: "header")
+      end
+
+      assert_raise ArgumentError, fn ->
+        XlsxWriter.write_rich_string(sheet, 0, 0, [{"Bold", [:bold]}], style: "header")
+      end
+    end
+
+    test "raises when both a style and a format are given" do
+      assert_raise ArgumentError, fn ->
+        XlsxWriter.new_sheet("Invoices")
+        |> XlsxWriter.write(0, 0, "Amount", style: "header", format: [:bold])
+      end
+    end
+  end
+
   describe "write/5 with nil value" do
     test "writes a nil value with a format as an empty string cell" do
       sheet =
@@ -226,6 +329,175 @@ defmodule XlsxWriterTest do
     end
   end
 
+  describe "outline grouping" do
+    test "writes nested and collapsed row and column groups" do
+      sheet =
+        Enum.reduce(0..9, XlsxWriter.new_sheet("P&L"), fn row, sheet ->
+          XlsxWriter.write(sheet, row, 0, "Line #{row}")
+        end)
+        |> XlsxWriter.group_rows(1, 8)
+        |> XlsxWriter.group_rows(1, 3, level: 2)
+        |> XlsxWriter.group_rows(5, 7, level: 2, collapsed: true)
+        |> XlsxWriter.group_rows(6, 6, level: 7)
+        |> XlsxWriter.group_columns(1, 3, collapsed: true)
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+      xml = outline_sheet_xml(content)
+
+      # Each row gets the highest level of the groups holding it, the rows of
+      # the collapsed group are hidden and the row after it is marked
+      assert Regex.scan(~r/<row r="(\d+)" spans="1:1"([^>]*)>/, xml, capture: :all_but_first) == [
+               ["1", ""],
+               ["2", ~s( outlineLevel="2")],
+               ["3", ~s( outlineLevel="2")],
+               ["4", ~s( outlineLevel="2")],
+               ["5", ~s( outlineLevel="1")],
+               ["6", ~s( hidden="1" outlineLevel="2")],
+               ["7", ~s( hidden="1" outlineLevel="7")],
+               ["8", ~s( hidden="1" outlineLevel="2")],
+               ["9", ~s( outlineLevel="1" collapsed="1")],
+               ["10", ""]
+             ]
+
+      assert xml =~ ~s(outlineLevelRow="7")
+
+      assert xml =~
+               ~s(<cols><col min="2" max="4" width="0" hidden="1" customWidth="1" outlineLevel="1"/>) <>
+                 ~s(<col min="5" max="5" width="9.140625" collapsed="1"/></cols>)
+    end
+
+    test "writes the summary row and column positions" do
+      sheet =
+        XlsxWriter.new_sheet("P&L")
+        |> XlsxWriter.write(0, 0, "Total")
+        |> XlsxWriter.group_rows(1, 3)
+        |> XlsxWriter.group_columns(1, 3)
+        |> XlsxWriter.set_outline_summary(rows: :above, columns: :left)
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+
+      assert outline_sheet_xml(content) =~
+               ~s(<sheetPr><outlinePr summaryBelow="0" summaryRight="0"/></sheetPr>)
+    end
+
+    test "leaves out the outline properties for the default summary positions" do
+      sheet =
+        XlsxWriter.new_sheet("P&L")
+        |> XlsxWriter.group_rows(1, 3)
+        |> XlsxWriter.set_outline_summary(rows: :below, columns: :right)
+
+      assert {:ok, content} = XlsxWriter.generate([sheet])
+      refute outline_sheet_xml(content) =~ "<outlinePr"
+    end
+
+    test "returns an error for an outline level above 7" do
+      sheet = XlsxWriter.new_sheet("P&L") |> XlsxWriter.group_rows(1, 3, level: 8)
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+
+    test "returns an error for an inverted column range" do
+      sheet = XlsxWriter.new_sheet("P&L") |> XlsxWriter.group_columns(3, 1)
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+  end
+
+  defp outline_sheet_xml(content) do
+    {:ok, files} = :zip.unzip(content, [:memory])
+    {_, xml} = List.keyfind(files, ~c"xl/worksheets/sheet1.xml", 0)
+    xml
+  end
+
+  describe "generate_file/3" do
+    @tag :tmp_dir
+    test "writes the xlsx file to disk", %{tmp_dir: dir} do
+      path = Path.join(dir, "report.xlsx")
+      sheet = XlsxWriter.new_sheet("Report") |> XlsxWriter.write(0, 0, "Hello")
+
+      assert :ok = XlsxWriter.generate_file([sheet], path)
+      assert <<80, 75, _::binary>> = File.read!(path)
+    end
+
+    @tag :tmp_dir
+    test "writes constant memory sheets and properties", %{tmp_dir: dir} do
+      path = Path.join(dir, "export.xlsx")
+
+      sheet =
+        Enum.reduce(0..99, XlsxWriter.new_sheet("Export"), fn row, sheet ->
+          XlsxWriter.write(sheet, row, 0, row)
+        end)
+
+      properties = %XlsxWriter.WorkbookProperties{title: "Export"}
+
+      assert :ok =
+               XlsxWriter.generate_file([sheet], path, memory: :constant, properties: properties)
+
+      assert <<80, 75, _::binary>> = File.read!(path)
+    end
+
+    @tag :tmp_dir
+    test "writes an encrypted file", %{tmp_dir: dir} do
+      path = Path.join(dir, "secret.xlsx")
+      sheet = XlsxWriter.new_sheet("Report") |> XlsxWriter.write(0, 0, "Hello")
+
+      assert :ok = XlsxWriter.generate_file([sheet], path, password: "secret")
+      assert <<0xD0, 0xCF, 0x11, 0xE0, _::binary>> = File.read!(path)
+    end
+
+    @tag :tmp_dir
+    test "returns an error when the file can't be written", %{tmp_dir: dir} do
+      path = Path.join([dir, "missing", "report.xlsx"])
+      sheet = XlsxWriter.new_sheet("Report") |> XlsxWriter.write(0, 0, "Hello")
+
+      assert {:error, _reason} = XlsxWriter.generate_file([sheet], path)
+    end
+  end
+
+  describe "memory modes" do
+    test "generates valid xlsx in constant and low memory mode" do
+      for mode <- [:constant, :low] do
+        sheet =
+          Enum.reduce(0..99, XlsxWriter.new_sheet("Export"), fn row, sheet ->
+            sheet
+            |> XlsxWriter.write(row, 0, "Item #{row}")
+            |> XlsxWriter.write(row, 1, row * 1.5, format: [{:num_format, "0.00"}])
+          end)
+
+        assert {:ok, content} = XlsxWriter.generate([sheet], memory: mode)
+        assert <<80, _>> <> _ = content
+      end
+    end
+
+    test "generates valid xlsx with a single constant memory sheet" do
+      export =
+        XlsxWriter.new_sheet("Export", memory: :constant)
+        |> XlsxWriter.write(0, 0, "Header", format: [:bold])
+        |> XlsxWriter.write(1, 0, "Value")
+
+      summary = XlsxWriter.new_sheet("Summary") |> XlsxWriter.write(0, 0, "Total")
+
+      assert {:ok, content} = XlsxWriter.generate([summary, export])
+      assert <<80, _>> <> _ = content
+    end
+
+    test "returns an error for row groups in constant memory mode" do
+      sheet =
+        XlsxWriter.new_sheet("Export", memory: :constant)
+        |> XlsxWriter.write(0, 0, "Header")
+        |> XlsxWriter.group_rows(1, 3)
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet])
+    end
+
+    test "returns an error for an invalid tempdir" do
+      sheet = XlsxWriter.new_sheet("Export") |> XlsxWriter.write(0, 0, "Header")
+
+      assert {:error, _reason} =
+               XlsxWriter.generate([sheet], memory: :low, tempdir: "/nonexistent/xlsx_writer")
+    end
+  end
+
   describe "set_autofilter/5" do
     test "generates valid xlsx with autofilter" do
       sheet =
@@ -1269,6 +1541,156 @@ defmodule XlsxWriterTest do
     end
   end
 
+  describe "defined names" do
+    test "writes workbook and sheet-scoped names" do
+      settings =
+        XlsxWriter.new_sheet("Settings")
+        |> XlsxWriter.write(0, 0, "North")
+        |> XlsxWriter.write(1, 0, "South")
+        |> XlsxWriter.write(1, 1, 0.2)
+
+      sheet =
+        XlsxWriter.new_sheet("Prices")
+        |> XlsxWriter.write(0, 0, 100)
+        |> XlsxWriter.write_formula(0, 1, "=A1*(1+TaxRate)")
+        |> XlsxWriter.write_formula(0, 2, "=ROWS(Regions)")
+        |> XlsxWriter.add_data_validation(1, 0, 10, 0, {:list, "=Regions"})
+
+      defined_names = [
+        {"TaxRate", "=Settings!$B$2"},
+        {"Regions", "=Settings!$A$1:$A$2"},
+        {"Prices!Local", "=Prices!$A$1"},
+        {"Settings!Local", "=Settings!$B$1"}
+      ]
+
+      assert {:ok, content} = XlsxWriter.generate([sheet, settings], defined_names: defined_names)
+
+      {:ok, files} = :zip.unzip(content, [:memory])
+      {_, workbook} = List.keyfind(files, ~c"xl/workbook.xml", 0)
+
+      # Sheet-scoped names carry the index of their sheet in the workbook
+      assert workbook =~
+               ~s(<definedNames>) <>
+                 ~s(<definedName name="Local" localSheetId="0">Prices!$A$1</definedName>) <>
+                 ~s(<definedName name="Local" localSheetId="1">Settings!$B$1</definedName>) <>
+                 ~s(<definedName name="Regions">Settings!$A$1:$A$2</definedName>) <>
+                 ~s(<definedName name="TaxRate">Settings!$B$2</definedName>) <>
+                 ~s(</definedNames>)
+    end
+
+    test "generates valid xlsx with defined names and properties" do
+      sheet = XlsxWriter.new_sheet("Data") |> XlsxWriter.write(0, 0, 1)
+      props = %XlsxWriter.WorkbookProperties{author: "Jane Doe"}
+
+      assert {:ok, content} =
+               XlsxWriter.generate([sheet],
+                 properties: props,
+                 defined_names: [{"Start", "=Data!$A$1"}]
+               )
+
+      assert <<80, _>> <> _ = content
+    end
+
+    test "returns an error for an invalid name" do
+      sheet = XlsxWriter.new_sheet("Data")
+
+      assert {:error, _reason} =
+               XlsxWriter.generate([sheet], defined_names: [{"1stValue", "=Data!$A$1"}])
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet], defined_names: [{"", "=Data!$A$1"}])
+    end
+
+    test "returns an error for a name scoped to an unknown sheet" do
+      sheet = XlsxWriter.new_sheet("Data")
+
+      assert {:error, _reason} =
+               XlsxWriter.generate([sheet], defined_names: [{"Missing!Name", "=Data!$A$1"}])
+    end
+  end
+
+  describe "workbook protection" do
+    test "generates valid xlsx with structure protection" do
+      sheet = XlsxWriter.new_sheet("Data") |> XlsxWriter.write(0, 0, "Hello")
+
+      assert {:ok, content} = XlsxWriter.generate([sheet], protect_workbook: true)
+      assert <<80, _>> <> _ = content
+    end
+
+    test "generates valid xlsx with a structure password, read-only flag and properties" do
+      sheet = XlsxWriter.new_sheet("Data") |> XlsxWriter.write(0, 0, "Hello")
+      props = %XlsxWriter.WorkbookProperties{author: "Jane Doe"}
+
+      assert {:ok, content} =
+               XlsxWriter.generate([sheet],
+                 properties: props,
+                 protect_workbook: [password: "secret"],
+                 read_only_recommended: true
+               )
+
+      assert <<80, _>> <> _ = content
+    end
+
+    test "writes the protection element before the book views" do
+      sheet = XlsxWriter.new_sheet("Data") |> XlsxWriter.write(0, 0, "Hello")
+
+      assert {:ok, content} =
+               XlsxWriter.generate([sheet], protect_workbook: [password: "password"])
+
+      {:ok, files} = :zip.unzip(content, [:memory])
+      {_, xml} = List.keyfind(files, ~c"xl/workbook.xml", 0)
+
+      assert [protection] = Regex.run(~r/<workbookProtection [^>]*\/>(?=<bookViews>)/, xml)
+      assert protection =~ ~s(lockStructure="1")
+      # Excel's legacy password hash of "password"
+      assert protection =~ ~s(workbookPassword="83AF")
+    end
+  end
+
+  describe "password encryption" do
+    test "generates an encrypted OLE container" do
+      sheet = XlsxWriter.new_sheet("Payroll") |> XlsxWriter.write(0, 0, 52_000)
+
+      assert {:ok, content} = XlsxWriter.generate([sheet], password: "s3cret")
+      assert <<0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1, _::binary>> = content
+
+      for stream <- [
+            "EncryptionInfo",
+            "EncryptedPackage",
+            "\x06DataSpaces",
+            "DataSpaceMap",
+            "StrongEncryptionDataSpace",
+            "StrongEncryptionTransform",
+            "\x06Primary"
+          ] do
+        assert :binary.match(content, utf16(stream)) != :nomatch, stream
+      end
+
+      assert content =~ ~s(<keyData saltSize="16" blockSize="16" keyBits="256" hashSize="64")
+      assert content =~ ~s(<p:encryptedKey spinCount="100000")
+      # The package itself is only stored encrypted
+      refute content =~ "xl/worksheets/sheet1.xml"
+    end
+
+    test "encrypts after applying workbook protection" do
+      sheet = XlsxWriter.new_sheet("Payroll") |> XlsxWriter.write(0, 0, 52_000)
+
+      assert {:ok, content} =
+               XlsxWriter.generate([sheet], password: "s3cret", protect_workbook: true)
+
+      assert <<0xD0, 0xCF, _::binary>> = content
+      assert :binary.match(content, utf16("EncryptedPackage")) != :nomatch
+      refute content =~ "workbookProtection"
+    end
+
+    test "returns an error for an empty password" do
+      sheet = XlsxWriter.new_sheet("Payroll")
+
+      assert {:error, _reason} = XlsxWriter.generate([sheet], password: "")
+    end
+  end
+
+  defp utf16(text), do: :unicode.characters_to_binary(text, :utf8, {:utf16, :little})
+
   describe "phase 1 features integration" do
     test "generates xlsx with all phase 1 features combined" do
       sheet =